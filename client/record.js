@@ -0,0 +1,67 @@
+// Offline recording of the replay, see start_recording in src/lib.rs. The playback clock steps a fixed
+// 1/fps per frame however long a frame takes to render, so every recording of a log is the same.
+
+// WebM through MediaRecorder. Frames are handed to the recorder no faster than real time; a frame that
+// takes longer than 1/fps to render is held on screen longer, so use the PNG sequence where exact
+// timing matters.
+export async function recordWebm(api, width, height, fps) {
+    const canvas = document.createElement("canvas");
+    canvas.width = width;
+    canvas.height = height;
+    const context = canvas.getContext("2d");
+    const stream = canvas.captureStream(0);
+    const track = stream.getVideoTracks()[0];
+    const recorder = new MediaRecorder(stream, {mimeType: "video/webm", videoBitsPerSecond: 16e6});
+    const chunks = [];
+    recorder.ondataavailable = (e) => chunks.push(e.data);
+    const stopped = new Promise((resolve) => recorder.onstop = resolve);
+
+    api.start_recording(width, height, fps);
+    recorder.start();
+    try {
+        const started = performance.now();
+        for (let frame = 0; ; frame++) {
+            const pixels = api.record_frame();
+            if (!pixels) break;
+            context.putImageData(new ImageData(new Uint8ClampedArray(pixels.buffer), width, height), 0, 0);
+            const due = started + frame * 1000 / fps;
+            await new Promise((resolve) => setTimeout(resolve, Math.max(0, due - performance.now())));
+            track.requestFrame();
+        }
+    } finally {
+        api.stop_recording();
+        recorder.stop();
+    }
+    await stopped;
+    save(new Blob(chunks, {type: "video/webm"}), "flight.webm");
+}
+
+// Numbered PNG files written into a folder the user picks, frame_00000.png onwards. Needs the File System
+// Access API; turn into a video with e.g. `ffmpeg -framerate 30 -i frame_%05d.png flight.mp4`.
+export async function recordPngSequence(api, width, height, fps) {
+    if (!window.showDirectoryPicker) throw "saving a PNG sequence needs showDirectoryPicker";
+    const directory = await window.showDirectoryPicker({mode: "readwrite"});
+
+    api.start_recording(width, height, fps);
+    try {
+        for (let frame = 0; ; frame++) {
+            const pixels = api.record_frame();
+            if (!pixels) break;
+            const file = await directory.getFileHandle(`frame_${String(frame).padStart(5, "0")}.png`, {create: true});
+            const writable = await file.createWritable();
+            await writable.write(api.encode_png(width, height, pixels));
+            await writable.close();
+        }
+    } finally {
+        api.stop_recording();
+    }
+}
+
+export function save(blob, name) {
+    const link = document.createElement("a");
+    link.href = URL.createObjectURL(blob);
+    link.download = name;
+    link.click();
+    // the download starts after click() returns, revoking straight away can cancel it
+    setTimeout(() => URL.revokeObjectURL(link.href), 60000);
+}
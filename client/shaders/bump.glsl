@@ -0,0 +1,12 @@
+// Bump mapping from screen space derivatives, so it works with the cube-cross uv layout of the mesh.
+vec3 perturb_normal(vec3 n, vec3 pos, float height) {
+    vec3 dpdx = dFdx(pos);
+    vec3 dpdy = dFdy(pos);
+    float dhdx = dFdx(height);
+    float dhdy = dFdy(height);
+    vec3 r1 = cross(dpdy, n);
+    vec3 r2 = cross(n, dpdx);
+    float det = dot(dpdx, r1);
+    vec3 grad = sign(det) * (dhdx * r1 + dhdy * r2);
+    return normalize(abs(det) * n - grad);
+}
@@ -0,0 +1,55 @@
+#version 300 es
+
+precision highp float;
+in vec3 frag_model_pos;
+in vec3 frag_world_pos;
+in vec2 frag_uv;
+
+out vec4 outColor;
+
+uniform sampler2D diffuse_tex;
+uniform sampler2D clouds_tex;
+uniform sampler2D night_tex;
+uniform sampler2D land_mask_tex;
+uniform sampler2D bump_tex;
+uniform mat4 rotate;
+uniform vec3 light;
+uniform vec3 camera_position;
+uniform float atmosphere;
+
+const vec3 ATMOSPHERE_COLOR = vec3(0.35, 0.6, 1.0);
+const float BUMP_HEIGHT = 0.02;
+
+#include "bump.glsl"
+
+void main() {
+    vec3 model_normal = normalize(frag_model_pos);
+    vec3 geo_normal = normalize((rotate * vec4(model_normal, 0)).xyz);
+    vec3 view_dir = normalize(camera_position - frag_world_pos);
+    float sun_angle = dot(geo_normal, light);
+    float day = smoothstep(-0.15, 0.25, sun_angle);
+    float rim = pow(1.0 - abs(dot(geo_normal, view_dir)), 4.0);
+
+    if (atmosphere > 0.0) {
+        outColor = vec4(ATMOSPHERE_COLOR, rim * (0.1 + day));
+        return;
+    }
+
+    float height = texture(bump_tex, frag_uv).r * BUMP_HEIGHT;
+    vec3 bumped = perturb_normal(model_normal, frag_model_pos, height);
+    vec3 normal = normalize((rotate * vec4(bumped, 0)).xyz);
+
+    vec3 albedo = texture(diffuse_tex, frag_uv).rgb;
+    float clouds = texture(clouds_tex, frag_uv).r;
+    float ocean = 1.0 - texture(land_mask_tex, frag_uv).r;
+
+    vec3 half_dir = normalize(light + view_dir);
+    float specular = ocean * pow(max(dot(geo_normal, half_dir), 0.0), 60.0) * 0.6 * day;
+
+    vec3 color = albedo * max(dot(normal, light), 0.0) + specular;
+    color = mix(color, vec3(max(sun_angle, 0.0)), clouds);
+    color += texture(night_tex, frag_uv).rgb * (1.0 - day) * (1.0 - clouds);
+    color += ATMOSPHERE_COLOR * rim * (0.2 + day) * 0.5;
+
+    outColor = vec4(color, 1);
+}
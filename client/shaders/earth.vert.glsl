@@ -0,0 +1,18 @@
+#version 300 es
+
+layout(location = 0) in vec3 position;
+in vec3 normal;
+in vec2 uv;
+
+out vec3 frag_model_pos;
+out vec3 frag_world_pos;
+out vec2 frag_uv;
+uniform mat4 mvp;
+uniform mat4 model;
+
+void main() {
+    frag_model_pos = position;
+    frag_world_pos = (model * vec4(position, 1)).xyz;
+    frag_uv = uv;
+    gl_Position = mvp * vec4(position, 1);
+}
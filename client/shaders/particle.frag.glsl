@@ -0,0 +1,13 @@
+#version 300 es
+
+precision highp float;
+in vec2 frag_corner;
+in vec4 frag_color;
+
+out vec4 outColor;
+
+void main() {
+    // soft round puff, the colour is premultiplied so alpha 0 adds light like a flame
+    float falloff = 1.0 - smoothstep(0.2, 1.0, length(frag_corner));
+    outColor = frag_color * falloff;
+}
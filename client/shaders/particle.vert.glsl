@@ -0,0 +1,20 @@
+#version 300 es
+
+layout(location = 0) in vec2 corner;
+// per instance: world position and diameter, then premultiplied colour
+layout(location = 1) in vec4 center_size;
+layout(location = 2) in vec4 color;
+
+out vec2 frag_corner;
+out vec4 frag_color;
+
+uniform mat4 view_proj;
+uniform vec3 camera_right;
+uniform vec3 camera_up;
+
+void main() {
+    frag_corner = corner;
+    frag_color = color;
+    vec3 position = center_size.xyz + (camera_right * corner.x + camera_up * corner.y) * (center_size.w * 0.5);
+    gl_Position = view_proj * vec4(position, 1);
+}
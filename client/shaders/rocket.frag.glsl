@@ -0,0 +1,25 @@
+#version 300 es
+
+precision highp float;
+in vec3 frag_normal;
+in vec2 frag_uv;
+in vec4 frag_shadow_pos;
+
+out vec4 outColor;
+
+uniform sampler2D tex;
+uniform highp sampler2DShadow shadow_map;
+uniform vec3 light;
+uniform float opacity;
+uniform float receive_shadow;
+// rgb blended over the texture by a, for telling flights apart
+uniform vec4 tint;
+
+#include "shadow_sampling.glsl"
+
+void main() {
+    float intensity = max(dot(light, frag_normal), 0.0) * shadow_factor() + 0.7;
+    vec4 color = texture(tex, frag_uv);
+    vec3 base = mix(color.zyx, tint.rgb, tint.a);
+    outColor = vec4(base * intensity, opacity);
+}
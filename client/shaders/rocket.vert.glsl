@@ -0,0 +1,19 @@
+#version 300 es
+
+layout(location = 0) in vec3 position;
+in vec3 normal;
+in vec2 uv;
+
+out vec3 frag_normal;
+out vec2 frag_uv;
+out vec4 frag_shadow_pos;
+uniform mat4 mvp;
+uniform mat4 rotate;
+uniform mat4 light_mvp;
+
+void main() {
+    frag_normal = (rotate * vec4(normal, 0)).xyz;
+    frag_uv = uv;
+    frag_shadow_pos = light_mvp * vec4(position, 1);
+    gl_Position = mvp * vec4(position, 1);
+}
@@ -0,0 +1,6 @@
+#version 300 es
+
+precision highp float;
+
+void main() {
+}
@@ -0,0 +1,9 @@
+#version 300 es
+
+layout(location = 0) in vec3 position;
+
+uniform mat4 light_mvp;
+
+void main() {
+    gl_Position = light_mvp * vec4(position, 1);
+}
@@ -0,0 +1,19 @@
+// Percentage closer filtered lookup into the sun's shadow map.
+// Needs `frag_shadow_pos`, `shadow_map` and `receive_shadow` declared by the including shader.
+
+float shadow_factor() {
+    vec3 proj = frag_shadow_pos.xyz / frag_shadow_pos.w * 0.5 + 0.5;
+    if (receive_shadow == 0.0 || any(lessThan(proj.xy, vec2(0))) || any(greaterThan(proj.xy, vec2(1)))) {
+        return 1.0;
+    }
+    // the shadow camera is fitted to the rocket, so the ground usually lies past its far plane
+    proj.z = min(proj.z, 1.0);
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_map, vec3(proj.xy + vec2(x, y) * texel, proj.z));
+        }
+    }
+    return lit / 9.0;
+}
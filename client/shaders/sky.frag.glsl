@@ -0,0 +1,25 @@
+#version 300 es
+
+precision highp float;
+in vec2 frag_ndc;
+
+out vec4 outColor;
+
+uniform sampler2D gradient;
+uniform mat4 inverse_view_proj;
+uniform vec3 light;
+uniform float altitude;
+
+void main() {
+    vec4 far = inverse_view_proj * vec4(frag_ndc, 1, 1);
+    vec3 dir = normalize(far.xyz / far.w);
+
+    // gradient rows run from sea level to the top of the table, columns from -0.2 to 1 in sin(elevation)
+    vec2 lookup = vec2((dir.z + 0.2) / 1.2, altitude);
+    vec3 color = texture(gradient, lookup).rgb;
+
+    float sun = max(dot(dir, light), 0.0);
+    color += vec3(1.0, 0.9, 0.7) * (pow(sun, 8.0) * 0.25 + smoothstep(0.9995, 0.9998, sun));
+
+    outColor = vec4(color, 1);
+}
@@ -0,0 +1,10 @@
+#version 300 es
+
+layout(location = 0) in vec3 position;
+
+out vec2 frag_ndc;
+
+void main() {
+    frag_ndc = position.xy;
+    gl_Position = vec4(position.xy, 1, 1);
+}
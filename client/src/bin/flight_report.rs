@@ -0,0 +1,50 @@
+// Native post-flight report: reads a flight log and writes the standard plots, a summary, the validation
+// report and the wind profile.
+//
+// usage: cargo run --features cli --bin flight_report -- [log.csv] [output dir] [state mapping, e.g. "1=pad,3=boost", "" for the default]
+//                                         [display units, e.g. "imperial" or "metric, ft"]
+
+use std::fs::File;
+use std::path::Path;
+use std::process::exit;
+
+use RustRocket::csvreader::get_csv_vec;
+use RustRocket::flight_state::StateMapping;
+use RustRocket::plot_graph::write_flight_report;
+use RustRocket::rocket_data::RocketData;
+use RustRocket::units::DisplayUnits;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let log = args.get(1).map(String::as_str).unwrap_or("csv/trimmed.csv");
+    let out_dir = args.get(2).map(String::as_str).unwrap_or("report");
+    let mapping = match args.get(3).filter(|text| !text.is_empty()).map(|text| StateMapping::parse(text)).unwrap_or_else(|| Ok(StateMapping::default())) {
+        Ok(mapping) => mapping,
+        Err(e) => {
+            eprintln!("state mapping: {}", e);
+            exit(1);
+        }
+    };
+
+    let units = match args.get(4).map(|text| DisplayUnits::parse(text)).unwrap_or_else(|| Ok(DisplayUnits::default())) {
+        Ok(units) => units,
+        Err(e) => {
+            eprintln!("display units: {}", e);
+            exit(1);
+        }
+    };
+
+    let rows: Vec<RocketData> = match File::open(log).map_err(|e| e.to_string()).and_then(|f| get_csv_vec(f).map_err(|e| e.to_string())) {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("{}: {}", log, e);
+            exit(1);
+        }
+    };
+
+    if let Err(e) = write_flight_report(&rows, &mapping, &units, Path::new(out_dir)) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+    println!("wrote report for {} rows to {}", rows.len(), out_dir);
+}
@@ -0,0 +1,94 @@
+// Converts, trims and resamples flight logs, and prints summary statistics. Formats are picked by extension:
+// .csv, .bin (little-endian RocketData records, see log_tools::RECORD_BYTES) and .jsonl.
+//
+// usage: cargo run --features cli --bin log_tool -- <command> ...
+//   convert  <in> <out>
+//   trim     <in> <out> [--from seconds] [--to seconds]
+//   trim     <in> <out> --states <from state>..<to state> [--mapping "1=pad,3=boost"]
+//   resample <in> <out> <rate Hz>
+//   stats    <in> [--mapping "1=pad,3=boost"]
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::process::exit;
+
+use RustRocket::flight_state::{FlightState, StateMapping};
+use RustRocket::log_tools::{log_statistics, read_log, resample, trim_states, trim_time, write_log, LogFormat};
+use RustRocket::rocket_data::RocketData;
+
+const USAGE: &str = "usage: log_tool convert <in> <out>
+       log_tool trim <in> <out> [--from seconds] [--to seconds] [--states from..to] [--mapping code=name,...]
+       log_tool resample <in> <out> <rate Hz>
+       log_tool stats <in> [--mapping code=name,...]";
+
+fn format_of(path: &str) -> Result<LogFormat, String> {
+    LogFormat::from_path(Path::new(path)).ok_or_else(|| format!("{}: unknown log format, expected .csv, .bin or .jsonl", path))
+}
+
+fn load(path: &str) -> Result<Vec<RocketData>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    read_log(format_of(path)?, BufReader::new(file)).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn save(path: &str, rows: &[RocketData]) -> Result<(), String> {
+    let format = format_of(path)?;
+    let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+    write_log(format, rows, BufWriter::new(file)).map_err(|e| format!("{}: {}", path, e))?;
+    println!("wrote {} rows to {}", rows.len(), path);
+    Ok(())
+}
+
+// Value after `--name`, if given.
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1)).map(String::as_str)
+}
+
+fn seconds_option(args: &[String], name: &str) -> Result<Option<f64>, String> {
+    option(args, name).map(|text| text.parse::<f64>().map_err(|_| format!("{}: bad number of seconds \"{}\"", name, text))).transpose()
+}
+
+fn mapping_option(args: &[String]) -> Result<StateMapping, String> {
+    option(args, "--mapping").map(StateMapping::parse).unwrap_or_else(|| Ok(StateMapping::default()))
+}
+
+fn state_range(text: &str) -> Result<(FlightState, FlightState), String> {
+    let (from, to) = text.split_once("..").ok_or_else(|| format!("expected from..to, got \"{}\"", text))?;
+    let state = |name: &str| FlightState::from_name(name).ok_or_else(|| format!("unknown state name \"{}\"", name.trim()));
+    Ok((state(from)?, state(to)?))
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let positional: Vec<&str> = args.iter().map(String::as_str).take_while(|arg| !arg.starts_with("--")).collect();
+    match positional.as_slice() {
+        ["convert", input, output] => save(output, &load(input)?),
+        ["trim", input, output] => {
+            let rows = load(input)?;
+            let trimmed = match option(args, "--states") {
+                Some(range) => {
+                    let (from, to) = state_range(range)?;
+                    trim_states(&rows, &mapping_option(args)?, from, to)?
+                }
+                None => trim_time(&rows, seconds_option(args, "--from")?, seconds_option(args, "--to")?),
+            };
+            save(output, &trimmed)
+        }
+        ["resample", input, output, rate] => {
+            let rate = rate.parse::<f64>().ok().filter(|rate| *rate > 0.0).ok_or_else(|| format!("bad rate \"{}\"", rate))?;
+            save(output, &resample(&load(input)?, rate))
+        }
+        ["stats", input] => {
+            print!("{}", log_statistics(&load(input)?, &mapping_option(args)?));
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
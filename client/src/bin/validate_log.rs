@@ -0,0 +1,31 @@
+// Checks a flight log for frozen sensors, timestamp problems, GPS dropouts and saturated readings.
+//
+// usage: cargo run --features cli --bin validate_log -- [log.csv] [--json]
+
+use std::fs::File;
+use std::process::exit;
+
+use RustRocket::csvreader::get_csv_vec;
+use RustRocket::rocket_data::RocketData;
+use RustRocket::validation::validate;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+    let log = args.iter().find(|arg| !arg.starts_with("--")).map(String::as_str).unwrap_or("csv/trimmed.csv");
+
+    let rows: Vec<RocketData> = match File::open(log).map_err(|e| e.to_string()).and_then(|f| get_csv_vec(f).map_err(|e| e.to_string())) {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("{}: {}", log, e);
+            exit(1);
+        }
+    };
+
+    let report = validate(&rows);
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}", report);
+    }
+}
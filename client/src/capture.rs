@@ -0,0 +1,65 @@
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlFramebuffer, WebGlRenderbuffer};
+
+use crate::webgl::{make_framebuffer, make_renderbuffer, read_rgba_pixels};
+
+// A framebuffer of its own size to render the scene into, so captures don't depend on the canvas size.
+pub struct OffscreenTarget {
+    pub framebuffer: WebGlFramebuffer,
+    color: WebGlRenderbuffer,
+    depth: WebGlRenderbuffer,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl OffscreenTarget {
+    pub fn new(ctx: &WebGl2RenderingContext, width: u32, height: u32) -> Result<OffscreenTarget, JsValue> {
+        let limit = ctx.get_parameter(WebGl2RenderingContext::MAX_RENDERBUFFER_SIZE)?.as_f64().unwrap_or(0.0) as u32;
+        if width == 0 || height == 0 || width > limit || height > limit {
+            return Err(JsValue::from_str(&format!("{}x{} is outside 1..{} pixels per side", width, height, limit)));
+        }
+        let (width, height) = (width as i32, height as i32);
+        let color = make_renderbuffer(ctx, WebGl2RenderingContext::RGBA8, width, height)
+            .ok_or_else(|| JsValue::from_str("Unable to create colour renderbuffer"))?;
+        let depth = make_renderbuffer(ctx, WebGl2RenderingContext::DEPTH_COMPONENT24, width, height)
+            .ok_or_else(|| JsValue::from_str("Unable to create depth renderbuffer"))?;
+        let framebuffer = make_framebuffer(ctx, &color, &depth)
+            .ok_or_else(|| JsValue::from_str("Unable to create offscreen framebuffer"))?;
+        Ok(OffscreenTarget { framebuffer, color, depth, width, height })
+    }
+
+    // RGBA bytes of what was last rendered into the target, top row first.
+    pub fn read_pixels(&self, ctx: &WebGl2RenderingContext) -> Result<Vec<u8>, JsValue> {
+        ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.framebuffer));
+        let pixels = read_rgba_pixels(ctx, self.width, self.height);
+        ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        pixels
+    }
+
+    pub fn delete(self, ctx: &WebGl2RenderingContext) {
+        ctx.delete_framebuffer(Some(&self.framebuffer));
+        ctx.delete_renderbuffer(Some(&self.color));
+        ctx.delete_renderbuffer(Some(&self.depth));
+    }
+}
+
+// A replay being rendered frame by frame at a fixed rate, independent of how fast frames are displayed.
+pub struct Recording {
+    pub target: OffscreenTarget,
+    pub frames_per_second: f64,
+    pub frame: u64,
+}
+
+pub fn encode_png(rgba: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    if rgba.len() != (width * height * 4) as usize {
+        return Err(format!("{} bytes is not {}x{} RGBA", rgba.len(), width, height));
+    }
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(rgba).map_err(|e| e.to_string())?;
+    drop(writer);
+    Ok(png)
+}
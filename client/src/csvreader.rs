@@ -5,6 +5,7 @@ use std::fs::File;
 use std::io;
 use csv;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use crate::rocket_data::RocketData;
 
 const CSV_FILE: &str = "csv/trimmed.csv";
@@ -18,3 +19,12 @@ pub fn get_rocket_data() -> Result<Vec<RocketData>, Box<dyn Error>> {
 pub fn get_csv_vec<D: DeserializeOwned, R: io::Read>(rdr: R) -> csv::Result<Vec<D>> {
     return csv::Reader::from_reader(rdr).into_deserialize().collect();
 }
+
+pub fn write_csv_vec<S: Serialize, W: io::Write>(rows: &[S], wtr: W) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(wtr);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
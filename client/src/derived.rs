@@ -0,0 +1,150 @@
+use crate::rocket_data::RocketData;
+
+// Half widths of the Savitzky-Golay windows. Differentiating twice amplifies the barometer noise a lot
+// more, so acceleration needs the longer window.
+const VELOCITY_WINDOW_SECONDS: f64 = 1.0;
+const ACCELERATION_WINDOW_SECONDS: f64 = 2.0;
+
+const GAS_CONSTANT_AIR: f64 = 287.05; // J/(kg K)
+const HEAT_CAPACITY_RATIO_AIR: f64 = 1.4;
+const CELSIUS_TO_KELVIN: f64 = 273.15;
+
+// Signals computed from the logged columns, one per row.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DerivedSample {
+    pub altitude: f64,              // m, smoothed barometer_altitude
+    pub vertical_velocity: f64,     // m/s, positive up
+    pub vertical_acceleration: f64, // m/s^2, positive up
+    pub speed_of_sound: f64,        // m/s
+    pub mach: f64,
+    pub air_density: f64,           // kg/m^3
+    pub dynamic_pressure: f64,      // Pa
+}
+
+// Median step between rows, so a dropped stretch or a timestamp that steps back doesn't skew it.
+pub fn sample_period_seconds(rows: &[RocketData]) -> f64 {
+    let mut steps: Vec<u32> = rows.windows(2)
+        .map(|w| w[1].timestamp.saturating_sub(w[0].timestamp))
+        .filter(|step| *step > 0)
+        .collect();
+    steps.sort_unstable();
+    steps.get(steps.len() / 2).map(|ms| *ms as f64 / 1000.0).unwrap_or(0.01)
+}
+
+// Quadratic least squares fit over rows i-m..=i+m, returned as (value, first derivative, second derivative)
+// at row i in per-sample units. The window shrinks symmetrically near the ends of the log.
+fn savitzky_golay(values: &[f64], i: usize, m: usize) -> (f64, f64, f64) {
+    let m = m.min(i).min(values.len() - 1 - i);
+    if m == 0 {
+        return (values[i], 0.0, 0.0);
+    }
+
+    // fit in the orthogonal basis 1, k, k^2 - mean(k^2) so each coefficient is an independent sum
+    let n = (2 * m + 1) as f64;
+    let mean_k2 = (m * (m + 1)) as f64 / 3.0;
+    let (mut sum, mut sum_k, mut sum_q, mut norm_k, mut norm_q) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for j in 0..=2 * m {
+        let k = j as f64 - m as f64;
+        let q = k * k - mean_k2;
+        let y = values[i + j - m];
+        sum += y;
+        sum_k += k * y;
+        sum_q += q * y;
+        norm_k += k * k;
+        norm_q += q * q;
+    }
+
+    let a1 = sum_k / norm_k;
+    let a2 = sum_q / norm_q;
+    (sum / n - a2 * mean_k2, a1, 2.0 * a2)
+}
+
+pub fn speed_of_sound(temperature_celsius: f64) -> f64 {
+    (HEAT_CAPACITY_RATIO_AIR * GAS_CONSTANT_AIR * (temperature_celsius + CELSIUS_TO_KELVIN)).sqrt()
+}
+
+// Ideal gas density from `pressure` in hPa, as logged.
+pub fn air_density(pressure_hpa: f64, temperature_celsius: f64) -> f64 {
+    pressure_hpa * 100.0 / (GAS_CONSTANT_AIR * (temperature_celsius + CELSIUS_TO_KELVIN))
+}
+
+// Mach number and dynamic pressure use the vertical velocity as the airspeed: the log has no horizontal
+// velocity, and on ascent, where both matter, the rocket is close to vertical. `temperature` is the
+// avionics bay sensor, it stands in for the outside air temperature.
+pub fn derive(rows: &[RocketData]) -> Vec<DerivedSample> {
+    let dt = sample_period_seconds(rows);
+    let velocity_half = (VELOCITY_WINDOW_SECONDS / dt).round() as usize;
+    let acceleration_half = (ACCELERATION_WINDOW_SECONDS / dt).round() as usize;
+    let altitude: Vec<f64> = rows.iter().map(|rd| rd.barometer_altitude).collect();
+
+    rows.iter().enumerate().map(|(i, rd)| {
+        let (smoothed, slope, _) = savitzky_golay(&altitude, i, velocity_half);
+        let (_, _, curvature) = savitzky_golay(&altitude, i, acceleration_half);
+        let vertical_velocity = slope / dt;
+        let speed_of_sound = speed_of_sound(rd.temperature);
+        let air_density = air_density(rd.pressure, rd.temperature);
+
+        DerivedSample {
+            altitude: smoothed,
+            vertical_velocity,
+            vertical_acceleration: curvature / (dt * dt),
+            speed_of_sound,
+            mach: vertical_velocity.abs() / speed_of_sound,
+            air_density,
+            dynamic_pressure: 0.5 * air_density * vertical_velocity * vertical_velocity,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10 ms rows of `altitude(t)` at a fixed temperature and pressure.
+    fn flight(seconds: f64, altitude: impl Fn(f64) -> f64, temperature: f64, pressure: f64) -> Vec<RocketData> {
+        (0..=(seconds * 100.0) as u32).map(|i| RocketData {
+            timestamp: 1000 + i * 10,
+            barometer_altitude: altitude(i as f64 / 100.0),
+            temperature,
+            pressure,
+            ..RocketData::default()
+        }).collect()
+    }
+
+    #[test]
+    fn recovers_the_derivatives_of_a_quadratic_climb() {
+        let rows = flight(10.0, |t| 100.0 + 50.0 * t - 4.9 * t * t, 15.0, 1013.25);
+        let derived = derive(&rows);
+        // away from the ends both windows are whole, and a quadratic fit of a quadratic is exact
+        for i in (200..rows.len() - 200).step_by(50) {
+            let t = i as f64 / 100.0;
+            assert!((derived[i].altitude - rows[i].barometer_altitude).abs() < 1e-6);
+            assert!((derived[i].vertical_velocity - (50.0 - 9.8 * t)).abs() < 1e-6, "velocity at {} s", t);
+            assert!((derived[i].vertical_acceleration + 9.8).abs() < 1e-6, "acceleration at {} s", t);
+        }
+    }
+
+    #[test]
+    fn median_period_ignores_dropouts_and_steps_back() {
+        let mut rows = flight(5.0, |_| 0.0, 15.0, 1013.25);
+        for rd in rows.iter_mut().skip(200) {
+            rd.timestamp += 2000;
+        }
+        rows[300].timestamp -= 50;
+        assert_eq!(sample_period_seconds(&rows), 0.01);
+        assert_eq!(sample_period_seconds(&rows[..1]), 0.01);
+    }
+
+    #[test]
+    fn mach_and_dynamic_pressure_at_a_known_state() {
+        // 200 m/s climbing through 1000 m of the standard atmosphere: 8.5 °C, 898.76 hPa, 1.1117 kg/m³,
+        // speed of sound 336.4 m/s
+        let rows = flight(6.0, |t| 1000.0 + 200.0 * t, 8.5, 898.76);
+        let sample = derive(&rows)[300];
+        assert!((sample.vertical_velocity - 200.0).abs() < 1e-6);
+        assert!((sample.speed_of_sound - 336.4).abs() < 0.1);
+        assert!((sample.air_density - 1.1117).abs() < 1e-3);
+        assert!((sample.mach - 200.0 / 336.4).abs() < 1e-3);
+        assert!((sample.dynamic_pressure - 0.5 * 1.1117 * 200.0 * 200.0).abs() < 25.0);
+    }
+}
@@ -0,0 +1,118 @@
+use nalgebra_glm as glm;
+use wasm_bindgen::JsValue;
+use web_sys::*;
+
+use crate::load_model::*;
+use crate::shaders::*;
+use crate::webgl::*;
+
+pub const EARTH_SHADER: ShaderFiles = ShaderFiles { vert: "earth.vert.glsl", frag: "earth.frag.glsl" };
+
+pub const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+// Radius of the "Earth" object in Earth_2K.obj, the clouds and atmosphere shells sit slightly above it.
+const EARTH_MODEL_RADIUS: f64 = 3.17403;
+const EARTH_OBJ: &str = "Models/Earth/Earth_2K.obj";
+
+pub struct Earth {
+    pub program: ShaderProgram,
+    pub surface: (WebGlVertexArrayObject, i32),
+    pub atmosphere: (WebGlVertexArrayObject, i32),
+    pub textures: Vec<(Option<WebGlTexture>, &'static str)>,
+    // scene space placement, computed once from the launch site
+    pub model: glm::Mat4,
+    pub rotate: glm::Mat4,
+}
+
+impl Earth {
+    pub async fn load(ctx: &WebGl2RenderingContext, launch_latitude: f64, launch_longitude: f64) -> Result<Earth, JsValue> {
+        let program = load_shader_program(ctx, &EARTH_SHADER).await?;
+
+        let (verts, norms, uvs) = load_mesh_object(EARTH_OBJ, "Earth").await?;
+        let surface = make_vao_vert_norm_uv(ctx, &program, &verts, &norms, &uvs)?;
+        let (verts, norms, uvs) = load_mesh_object(EARTH_OBJ, "Atmosphere").await?;
+        let atmosphere = make_vao_vert_norm_uv(ctx, &program, &verts, &norms, &uvs)?;
+
+        let mut textures = Vec::new();
+        for (path, uniform) in [
+            ("Models/Earth/Textures/Diffuse_2K.png", "diffuse_tex"),
+            ("Models/Earth/Textures/Clouds_2K.png", "clouds_tex"),
+            ("Models/Earth/Textures/Night_lights_2K.png", "night_tex"),
+            ("Models/Earth/Textures/Ocean_Mask_2K.png", "land_mask_tex"),
+            ("Models/Earth/Textures/Bump_2K.png", "bump_tex"),
+        ].iter() {
+            let (data, width, height) = load_png(path).await?;
+            textures.push((make_texture_rgba(ctx, data.as_slice(), width, height), *uniform));
+        }
+
+        let mut earth = Earth {
+            program,
+            surface,
+            atmosphere,
+            textures,
+            model: glm::identity(),
+            rotate: glm::identity(),
+        };
+        earth.set_launch_site(launch_latitude, launch_longitude);
+        Ok(earth)
+    }
+
+    // Turns the globe so the launch site is under the scene origin.
+    pub fn set_launch_site(&mut self, latitude: f64, longitude: f64) {
+        let scale = (EARTH_RADIUS_METERS / EARTH_MODEL_RADIUS) as f32;
+        self.rotate = earth_rotation(latitude, longitude);
+        self.model =
+            glm::translate(&glm::identity(), &glm::vec3(0.0, 0.0, -EARTH_RADIUS_METERS as f32)) *
+            self.rotate *
+            glm::scale(&glm::identity(), &glm::vec3(scale, scale, scale));
+    }
+
+    // Draws the globe into the colour buffer with its own depth range; the caller clears depth afterwards.
+    pub fn render(&self, ctx: &WebGl2RenderingContext, view_proj: &glm::Mat4, camera_position: &glm::Vec3, sun_direction: &glm::Vec3) {
+        let mvp = view_proj * self.model;
+
+        self.program.use_program(ctx);
+        for (i, (texture, uniform)) in self.textures.iter().enumerate() {
+            self.program.set_texture(ctx, uniform, texture.as_ref(), i as u32);
+        }
+        self.program.set_mat4(ctx, "mvp", &mvp);
+        self.program.set_mat4(ctx, "model", &self.model);
+        self.program.set_mat4(ctx, "rotate", &self.rotate);
+        self.program.set_vec3(ctx, "light", sun_direction);
+        self.program.set_vec3(ctx, "camera_position", camera_position);
+
+        self.program.set_f32(ctx, "atmosphere", 0.0);
+        ctx.bind_vertex_array(Some(&self.surface.0));
+        ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, self.surface.1);
+
+        // the atmosphere shell is drawn from the inside so it glows around the limb and along the horizon
+        self.program.set_f32(ctx, "atmosphere", 1.0);
+        ctx.cull_face(WebGl2RenderingContext::FRONT);
+        ctx.depth_mask(false);
+        ctx.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE);
+        ctx.bind_vertex_array(Some(&self.atmosphere.0));
+        ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, self.atmosphere.1);
+        ctx.blend_func_separate(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA, WebGl2RenderingContext::ONE, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
+        ctx.depth_mask(true);
+        ctx.cull_face(WebGl2RenderingContext::BACK);
+    }
+}
+
+// Rotation taking Earth_2K.obj model axes into the scene's east-north-up frame at the launch site.
+fn earth_rotation(latitude: f64, longitude: f64) -> glm::Mat4 {
+    let lat = latitude.to_radians() as f32;
+    let lon = longitude.to_radians() as f32;
+
+    // The mesh is y-up: model x is ECEF y, model y is ECEF z (north pole) and model z is ECEF x (lon 0).
+    let ecef_from_model = glm::mat3(
+        0.0, 0.0, 1.0,
+        1.0, 0.0, 0.0,
+        0.0, 1.0, 0.0,
+    );
+    let enu_from_ecef = glm::mat3(
+        -lon.sin(), lon.cos(), 0.0,
+        -lat.sin() * lon.cos(), -lat.sin() * lon.sin(), lat.cos(),
+        lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin(),
+    );
+
+    glm::mat3_to_mat4(&(enu_from_ecef * ecef_from_model))
+}
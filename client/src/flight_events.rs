@@ -0,0 +1,284 @@
+use crate::derived::{derive, sample_period_seconds};
+use crate::flight_state::{FlightState, StateTimeline};
+use crate::plot_graph::acceleration_magnitude;
+use crate::rocket_data::RocketData;
+
+// Sustained axial acceleration that counts as the motor lighting, in g.
+const LIFTOFF_ACCEL_G: f64 = 3.0;
+// Axial acceleration below this after liftoff means the motor is out and drag is decelerating the rocket.
+const BURNOUT_ACCEL_G: f64 = 0.0;
+// How long a threshold has to hold before it counts, so single noisy samples don't trigger events.
+const SUSTAIN_SECONDS: f64 = 0.1;
+// Acceleration magnitude that counts as a deployment shock. Descent noise peaks around 3 g.
+const DEPLOY_SPIKE_G: f64 = 2.5;
+// Acceleration magnitude of the touchdown shock.
+const LANDING_SPIKE_G: f64 = 5.0;
+// Altitude band the barometer has to stay in for LANDED_SECONDS to count as on the ground. The sample log
+// wanders about 4 m on the ground, under the main it still drops 60 m in that time.
+const LANDED_BAND_METERS: f64 = 5.0;
+const LANDED_SECONDS: f64 = 5.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    Liftoff,
+    Burnout,
+    Apogee,
+    Drogue,
+    Main,
+    Landing,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Liftoff => "liftoff",
+            EventKind::Burnout => "burnout",
+            EventKind::Apogee => "apogee",
+            EventKind::Drogue => "drogue",
+            EventKind::Main => "main",
+            EventKind::Landing => "landing",
+        }
+    }
+
+    // Logged states that mark the same moment, the first phase of any of them is the logged time.
+    fn logged_states(&self) -> &'static [FlightState] {
+        match self {
+            EventKind::Liftoff => &[FlightState::LaunchDetect, FlightState::Boost],
+            EventKind::Burnout => &[FlightState::BurnoutDetect, FlightState::Coast],
+            EventKind::Apogee => &[FlightState::ApogeeDetect, FlightState::Apogee],
+            EventKind::Drogue => &[FlightState::DrogueDetect, FlightState::Drogue],
+            EventKind::Main => &[FlightState::MainDetect, FlightState::Main],
+            EventKind::Landing => &[FlightState::LandedDetect, FlightState::Landed],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FlightEvent {
+    pub kind: EventKind,
+    pub row: usize,
+    pub timestamp: u32,
+    // 0 to 1, how clearly the signal showed the event
+    pub confidence: f64,
+}
+
+// A detected event next to the matching logged state transition, `difference_ms` is detected - logged.
+#[derive(Clone, Debug)]
+pub struct EventComparison {
+    pub kind: EventKind,
+    pub detected: Option<u32>,
+    pub logged: Option<u32>,
+    pub difference_ms: Option<i64>,
+}
+
+// Acceleration along the rocket's long axis in g, positive up. The flight computer is mounted with -y up.
+pub fn axial_acceleration(rd: &RocketData) -> f64 {
+    -rd.ay
+}
+
+fn samples(rows: &[RocketData], seconds: f64) -> usize {
+    ((seconds / sample_period_seconds(rows)).round() as usize).max(1)
+}
+
+fn clamp01(x: f64) -> f64 {
+    x.clamp(0.0, 1.0)
+}
+
+// First row at or after `start` where `condition` holds for `sustain` rows in a row.
+fn first_sustained(rows: &[RocketData], start: usize, sustain: usize, condition: impl Fn(&RocketData) -> bool) -> Option<usize> {
+    let mut run = 0;
+    for (i, rd) in rows.iter().enumerate().skip(start) {
+        run = if condition(rd) { run + 1 } else { 0 };
+        if run == sustain {
+            return Some(i + 1 - sustain);
+        }
+    }
+    None
+}
+
+// Row of the largest acceleration magnitude in `range`, if it clears `threshold`.
+fn largest_spike(rows: &[RocketData], range: std::ops::Range<usize>, threshold: f64) -> Option<(usize, f64)> {
+    let range = range.start.min(rows.len())..range.end.min(rows.len());
+    range.map(|i| (i, acceleration_magnitude(&rows[i]) as f64))
+        .fold(None, |best: Option<(usize, f64)>, (i, g)| match best {
+            Some(b) if b.1 >= g => Some(b),
+            _ => Some((i, g)),
+        })
+        .filter(|spike| spike.1 > threshold)
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 { 0.0 } else { sum / count as f64 }
+}
+
+// Descent rate in m/s (positive down) averaged over `seconds` after `start`.
+fn descent_rate(rows: &[RocketData], altitude: &[f64], start: usize, seconds: f64) -> f64 {
+    let end = (start + samples(rows, seconds)).min(rows.len() - 1);
+    if end <= start {
+        return 0.0;
+    }
+    let dt = rows[end].timestamp.saturating_sub(rows[start].timestamp) as f64 / 1000.0;
+    if dt <= 0.0 {
+        return 0.0;
+    }
+    (altitude[start] - altitude[end]) / dt
+}
+
+fn event(rows: &[RocketData], kind: EventKind, row: usize, confidence: f64) -> FlightEvent {
+    FlightEvent { kind, row, timestamp: rows[row].timestamp, confidence: clamp01(confidence) }
+}
+
+// Row where the motor lit, the first sustained stretch of upward acceleration.
+pub fn detect_liftoff(rows: &[RocketData]) -> Option<usize> {
+    first_sustained(rows, 0, samples(rows, SUSTAIN_SECONDS), |rd| axial_acceleration(rd) > LIFTOFF_ACCEL_G)
+}
+
+// Finds the flight events from the accelerometer and barometer alone, in flight order. Events that depend
+// on an earlier one are skipped when it wasn't found.
+pub fn detect_events(rows: &[RocketData]) -> Vec<FlightEvent> {
+    let mut events = Vec::new();
+    let sustain = samples(rows, SUSTAIN_SECONDS);
+    let altitude: Vec<f64> = derive(rows).iter().map(|sample| sample.altitude).collect();
+
+    let liftoff = match detect_liftoff(rows) {
+        Some(row) => row,
+        None => return events,
+    };
+    // a real launch climbs, a bumped pad doesn't
+    let climb = altitude[(liftoff + samples(rows, 3.0)).min(rows.len() - 1)] - altitude[liftoff];
+    events.push(event(rows, EventKind::Liftoff, liftoff, climb / 100.0));
+
+    let burnout = match first_sustained(rows, liftoff + sustain, sustain, |rd| axial_acceleration(rd) < BURNOUT_ACCEL_G) {
+        Some(row) => row,
+        None => return events,
+    };
+    let window = samples(rows, 0.5);
+    let thrust = mean(rows[burnout.saturating_sub(window)..burnout].iter().map(axial_acceleration));
+    let coast = mean(rows[burnout..(burnout + window).min(rows.len())].iter().map(axial_acceleration));
+    events.push(event(rows, EventKind::Burnout, burnout, (thrust - coast) / 5.0));
+
+    // landing bounds the search for apogee and the deployments
+    let settle = samples(rows, LANDED_SECONDS);
+    let landed = (burnout..rows.len().saturating_sub(settle)).find(|i| {
+        let (lo, hi) = rows[*i..i + settle].iter()
+            .fold((f64::MAX, f64::MIN), |(lo, hi), rd| (lo.min(rd.barometer_altitude), hi.max(rd.barometer_altitude)));
+        hi - lo < LANDED_BAND_METERS
+    }).unwrap_or(rows.len() - 1);
+
+    let apogee = (burnout..landed.max(burnout + 1)).fold(burnout, |best, i| if altitude[i] > altitude[best] { i } else { best });
+    let drop = altitude[apogee] - altitude[(apogee + samples(rows, 5.0)).min(rows.len() - 1)];
+    events.push(event(rows, EventKind::Apogee, apogee, drop / 20.0));
+
+    // the drogue charge fires once the rocket has stopped climbing, a shock before the peak is the
+    // airframe buffeting or a pressure pulse through the avionics bay
+    let drogue = largest_spike(rows, apogee + 1..apogee + samples(rows, 10.0), DEPLOY_SPIKE_G);
+    if let Some((row, g)) = drogue {
+        events.push(event(rows, EventKind::Drogue, row, (g - DEPLOY_SPIKE_G) / DEPLOY_SPIKE_G + 0.5));
+    }
+
+    // the main opens somewhere on the way down and slows the descent, take the biggest shock in between
+    let search_start = drogue.map(|d| d.0).unwrap_or(apogee) + samples(rows, 5.0);
+    let search_end = landed.saturating_sub(samples(rows, 5.0));
+    if let Some((row, g)) = largest_spike(rows, search_start..search_end, DEPLOY_SPIKE_G) {
+        let before = descent_rate(rows, &altitude, row.saturating_sub(samples(rows, 10.0)), 10.0);
+        let after = descent_rate(rows, &altitude, row + samples(rows, 2.0), 10.0);
+        let slowdown = if before > 0.0 { (before - after) / before } else { 0.0 };
+        let confidence = 0.5 * clamp01((g - DEPLOY_SPIKE_G) / DEPLOY_SPIKE_G) + 0.5 * clamp01(slowdown / 0.5);
+        events.push(event(rows, EventKind::Main, row, confidence));
+    }
+
+    if landed + 1 < rows.len() {
+        // prefer the touchdown shock, it is sharper than the altitude settling
+        let around = landed.saturating_sub(samples(rows, 3.0))..landed + samples(rows, 3.0);
+        match largest_spike(rows, around, LANDING_SPIKE_G) {
+            Some((row, _)) => events.push(event(rows, EventKind::Landing, row, 1.0)),
+            None => events.push(event(rows, EventKind::Landing, landed, 0.7)),
+        }
+    }
+
+    events
+}
+
+pub fn compare_with_log(events: &[FlightEvent], timeline: &StateTimeline) -> Vec<EventComparison> {
+    [EventKind::Liftoff, EventKind::Burnout, EventKind::Apogee, EventKind::Drogue, EventKind::Main, EventKind::Landing]
+        .iter()
+        .map(|kind| {
+            let detected = events.iter().find(|e| e.kind == *kind).map(|e| e.timestamp);
+            let logged = timeline.phases.iter()
+                .find(|phase| kind.logged_states().contains(&phase.state))
+                .map(|phase| phase.start_timestamp);
+            let difference_ms = match (detected, logged) {
+                (Some(d), Some(l)) => Some(d as i64 - l as i64),
+                _ => None,
+            };
+            EventComparison { kind: *kind, detected, logged, difference_ms }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csvreader::get_rocket_data;
+    use crate::flight_state::StateMapping;
+
+    fn find(events: &[FlightEvent], kind: EventKind) -> &FlightEvent {
+        events.iter().find(|e| e.kind == kind).unwrap_or_else(|| panic!("{} not detected", kind.name()))
+    }
+
+    #[test]
+    fn detects_every_event_in_sample_flight() {
+        let rows = get_rocket_data().unwrap();
+        let events = detect_events(&rows);
+
+        let expected = [
+            (EventKind::Liftoff, 1445700, 1446000),
+            (EventKind::Burnout, 1447000, 1447400),
+            (EventKind::Apogee, 1467500, 1469500),
+            (EventKind::Drogue, 1468000, 1470000),
+            (EventKind::Main, 1571000, 1573000),
+            (EventKind::Landing, 1632000, 1633500),
+        ];
+        for (kind, from, to) in expected.iter() {
+            let event = find(&events, *kind);
+            assert!((*from..*to).contains(&event.timestamp), "{} at {}", kind.name(), event.timestamp);
+            assert!(event.confidence > 0.5 && event.confidence <= 1.0, "{} confidence {}", kind.name(), event.confidence);
+        }
+    }
+
+    #[test]
+    fn events_are_in_flight_order() {
+        let rows = get_rocket_data().unwrap();
+        let events = detect_events(&rows);
+        let row = |kind| find(&events, kind).row;
+
+        assert_eq!(events.len(), 6);
+        assert!(row(EventKind::Liftoff) < row(EventKind::Burnout));
+        assert!(row(EventKind::Burnout) < row(EventKind::Apogee));
+        assert!(row(EventKind::Apogee) < row(EventKind::Drogue));
+        assert!(row(EventKind::Drogue) < row(EventKind::Main));
+        assert!(row(EventKind::Main) < row(EventKind::Landing));
+    }
+
+    #[test]
+    fn compares_against_logged_states() {
+        let rows = get_rocket_data().unwrap();
+        let timeline = StateTimeline::from_log(&rows, &StateMapping::default());
+        let comparison = compare_with_log(&detect_events(&rows), &timeline);
+
+        let liftoff = comparison.iter().find(|c| c.kind == EventKind::Liftoff).unwrap();
+        assert_eq!(liftoff.logged, Some(1445830));
+        assert!(liftoff.difference_ms.unwrap().abs() < 200);
+
+        // the flight computer only commits to burnout a few seconds after the motor is out
+        let burnout = comparison.iter().find(|c| c.kind == EventKind::Burnout).unwrap();
+        assert!(burnout.difference_ms.unwrap() < 0);
+
+        // the sample log never reaches the landed states
+        let landing = comparison.iter().find(|c| c.kind == EventKind::Landing).unwrap();
+        assert!(landing.detected.is_some());
+        assert_eq!(landing.logged, None);
+        assert_eq!(landing.difference_ms, None);
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::rocket_data::RocketData;
+
+// Flight phase behind a raw `rocket_state` code. The *Detect states are the flight computer waiting for a
+// condition to hold for a few samples before committing to the next phase.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum FlightState {
+    Init,
+    Pad,
+    LaunchDetect,
+    Boost,
+    BurnoutDetect,
+    Coast,
+    ApogeeDetect,
+    Apogee,
+    DrogueDetect,
+    Drogue,
+    MainDetect,
+    Main,
+    LandedDetect,
+    Landed,
+    Abort,
+    Unknown(u32),
+}
+
+// Every state with a name, in flight order.
+pub const NAMED_STATES: [FlightState; 15] = [
+    FlightState::Init,
+    FlightState::Pad,
+    FlightState::LaunchDetect,
+    FlightState::Boost,
+    FlightState::BurnoutDetect,
+    FlightState::Coast,
+    FlightState::ApogeeDetect,
+    FlightState::Apogee,
+    FlightState::DrogueDetect,
+    FlightState::Drogue,
+    FlightState::MainDetect,
+    FlightState::Main,
+    FlightState::LandedDetect,
+    FlightState::Landed,
+    FlightState::Abort,
+];
+
+impl FlightState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FlightState::Init => "init",
+            FlightState::Pad => "pad",
+            FlightState::LaunchDetect => "launch detect",
+            FlightState::Boost => "boost",
+            FlightState::BurnoutDetect => "burnout detect",
+            FlightState::Coast => "coast",
+            FlightState::ApogeeDetect => "apogee detect",
+            FlightState::Apogee => "apogee",
+            FlightState::DrogueDetect => "drogue detect",
+            FlightState::Drogue => "drogue",
+            FlightState::MainDetect => "main detect",
+            FlightState::Main => "main",
+            FlightState::LandedDetect => "landed detect",
+            FlightState::Landed => "landed",
+            FlightState::Abort => "abort",
+            FlightState::Unknown(_) => "unknown",
+        }
+    }
+
+    // Accepts the names above with spaces, underscores or dashes, in any case.
+    pub fn from_name(name: &str) -> Option<FlightState> {
+        let name = name.trim().to_lowercase().replace(['_', '-'], " ");
+        NAMED_STATES.iter().copied().find(|state| state.name() == name)
+    }
+
+    // Position in NAMED_STATES, with unknown codes after the last named state.
+    pub fn ordinal(&self) -> usize {
+        NAMED_STATES.iter().position(|state| state == self).unwrap_or(NAMED_STATES.len())
+    }
+
+    // Whether the rocket has left the pad, i.e. everything from launch detection on.
+    pub fn in_flight(&self) -> bool {
+        !matches!(self, FlightState::Init | FlightState::Pad | FlightState::Abort | FlightState::Unknown(_))
+    }
+}
+
+impl fmt::Display for FlightState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlightState::Unknown(code) => write!(f, "state {}", code),
+            state => write!(f, "{}", state.name()),
+        }
+    }
+}
+
+// Raw `rocket_state` code to FlightState. Codes without an entry come out as FlightState::Unknown.
+#[derive(Clone, Debug)]
+pub struct StateMapping {
+    codes: HashMap<u32, FlightState>,
+}
+
+// The codes the bundled sample log (csv/trimmed.csv) actually uses, read off against its sensors: 1 on the pad,
+// 2 for the 0.3 s as acceleration builds, 3 for the next 4.7 s, 4 for 0.3 s after that, 5 coasting to the
+// barometer peak, and 7 from 2.4 s after the peak through the whole descent to the ground. The motor burns
+// out early in code 3, the accelerometer sits at its -24 g limit until about 7.1 s and ay turns positive at
+// 7.25 s, so code 3 is the burn plus the first 3.6 s of coast and code 4 is not the burnout itself. Code 7 is
+// therefore the descent under the drogue rather than a one-off apogee state; the log never leaves it, so
+// main deployment and landing have no code of their own. Other codes are not backed by any log and come out
+// as FlightState::Unknown, use StateMapping::parse for other flight computers.
+const DEFAULT_CODES: [(u32, FlightState); 6] = [
+    (1, FlightState::Pad),
+    (2, FlightState::LaunchDetect),
+    (3, FlightState::Boost),
+    (4, FlightState::BurnoutDetect),
+    (5, FlightState::Coast),
+    (7, FlightState::Drogue),
+];
+
+impl Default for StateMapping {
+    fn default() -> Self {
+        StateMapping { codes: DEFAULT_CODES.iter().copied().collect() }
+    }
+}
+
+impl StateMapping {
+    // Parses "code=name" pairs separated by commas or newlines, e.g. "1=pad, 3=boost, 7=drogue".
+    pub fn parse(text: &str) -> Result<StateMapping, String> {
+        let mut codes = HashMap::new();
+        for entry in text.split([',', '\n']).map(str::trim).filter(|e| !e.is_empty()) {
+            let (code, name) = entry.split_once('=').ok_or_else(|| format!("expected code=name, got \"{}\"", entry))?;
+            let code = code.trim().parse::<u32>().map_err(|_| format!("bad state code \"{}\"", code.trim()))?;
+            let state = FlightState::from_name(name).ok_or_else(|| format!("unknown state name \"{}\"", name.trim()))?;
+            codes.insert(code, state);
+        }
+        Ok(StateMapping { codes })
+    }
+
+    pub fn state(&self, code: u32) -> FlightState {
+        self.codes.get(&code).copied().unwrap_or(FlightState::Unknown(code))
+    }
+}
+
+// A run of consecutive rows in the same state. `end_row` is exclusive.
+#[derive(Clone, Debug)]
+pub struct Phase {
+    pub state: FlightState,
+    pub start_row: usize,
+    pub end_row: usize,
+    pub start_timestamp: u32,
+    pub end_timestamp: u32,
+}
+
+pub struct StateTimeline {
+    pub phases: Vec<Phase>,
+}
+
+impl StateTimeline {
+    pub fn from_log(rows: &[RocketData], mapping: &StateMapping) -> StateTimeline {
+        let mut phases: Vec<Phase> = Vec::new();
+        for (i, rd) in rows.iter().enumerate() {
+            let state = mapping.state(rd.rocket_state);
+            match phases.last_mut() {
+                Some(phase) if phase.state == state => {
+                    phase.end_row = i + 1;
+                    phase.end_timestamp = rd.timestamp;
+                }
+                _ => phases.push(Phase {
+                    state,
+                    start_row: i,
+                    end_row: i + 1,
+                    start_timestamp: rd.timestamp,
+                    end_timestamp: rd.timestamp,
+                }),
+            }
+        }
+        StateTimeline { phases }
+    }
+
+    pub fn phase_at_row(&self, row: usize) -> Option<&Phase> {
+        let i = self.phases.partition_point(|phase| phase.end_row <= row);
+        self.phases.get(i).or_else(|| self.phases.last())
+    }
+
+    // Start of the first phase that is in flight, the zero of mission time.
+    pub fn liftoff_timestamp(&self) -> Option<u32> {
+        self.phases.iter().find(|phase| phase.state.in_flight()).map(|phase| phase.start_timestamp)
+    }
+
+    pub fn first(&self, state: FlightState) -> Option<&Phase> {
+        self.phases.iter().find(|phase| phase.state == state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csvreader::get_rocket_data;
+
+    #[test]
+    fn default_mapping_matches_sample_flight() {
+        let rows = get_rocket_data().unwrap();
+        let timeline = StateTimeline::from_log(&rows, &StateMapping::default());
+        let states: Vec<FlightState> = timeline.phases.iter().map(|phase| phase.state).collect();
+        assert_eq!(states, vec![FlightState::Pad, FlightState::LaunchDetect, FlightState::Boost,
+                                FlightState::BurnoutDetect, FlightState::Coast, FlightState::Drogue]);
+
+        // axial acceleration is -ay in g: the motor pushes ay negative and drag turns it positive once the
+        // motor is out, which happens inside boost rather than at its end
+        let boost = timeline.first(FlightState::Boost).unwrap();
+        let launch = timeline.first(FlightState::LaunchDetect).unwrap();
+        let burnout = (launch.start_row..rows.len()).find(|i| rows[*i].ay > 0.0).unwrap();
+        assert!(boost.start_row < burnout && burnout < boost.end_row);
+        assert_eq!(rows[burnout].timestamp - rows[0].timestamp, 7250);
+        assert!(rows[burnout..boost.end_row].iter().all(|rd| rd.ay > 0.0));
+        let coast = timeline.first(FlightState::Coast).unwrap();
+        let coast_ay = rows[coast.start_row..coast.end_row].iter().map(|rd| rd.ay).sum::<f64>() / (coast.end_row - coast.start_row) as f64;
+        assert!(coast_ay > -1.0, "mean ay {} while coasting", coast_ay);
+
+        // descent starts after the highest barometer reading and runs to the end of the log
+        let peak = (0..rows.len()).max_by(|a, b| rows[*a].barometer_altitude.total_cmp(&rows[*b].barometer_altitude)).unwrap();
+        let drogue = timeline.first(FlightState::Drogue).unwrap();
+        assert!(timeline.first(FlightState::Coast).unwrap().start_row < peak);
+        assert!(drogue.start_row > peak);
+        assert_eq!(drogue.end_row, rows.len());
+        assert!(rows[drogue.end_row - 1].barometer_altitude < rows[peak].barometer_altitude - 2500.0);
+    }
+
+    #[test]
+    fn unmapped_codes_are_unknown() {
+        let mapping = StateMapping::default();
+        assert_eq!(mapping.state(0), FlightState::Unknown(0));
+        assert_eq!(mapping.state(6), FlightState::Unknown(6));
+        assert_eq!(StateMapping::parse("6=apogee").unwrap().state(6), FlightState::Apogee);
+    }
+}
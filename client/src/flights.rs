@@ -0,0 +1,83 @@
+use crate::derived::{derive, DerivedSample};
+use crate::flight_events::{detect_events, EventKind};
+use crate::flight_state::{StateMapping, StateTimeline};
+use crate::kalman::{fuse_altitude, AltitudeEstimate};
+use crate::rocket_data::RocketData;
+
+// One colour per loaded flight, the flight started with `start` first. Extra flights cycle through the rest.
+pub const FLIGHT_COLORS: [(u8, u8, u8); 6] = [
+    (40, 90, 255),
+    (240, 130, 20),
+    (40, 170, 60),
+    (200, 50, 200),
+    (20, 170, 170),
+    (150, 90, 40),
+];
+
+// An imported simulation, grey so it reads as the reference rather than another flight.
+pub const SIMULATION_COLOR: (u8, u8, u8) = (110, 110, 110);
+
+pub fn flight_color(index: usize) -> (u8, u8, u8) {
+    match index {
+        0 => FLIGHT_COLORS[0],
+        i => FLIGHT_COLORS[1 + (i - 1) % (FLIGHT_COLORS.len() - 1)],
+    }
+}
+
+// Start of flight from the logged states, or from the detected liftoff when the mapping has no flight
+// states, or the first row.
+pub fn liftoff_timestamp(rows: &[RocketData], timeline: &StateTimeline) -> u32 {
+    timeline.liftoff_timestamp()
+        .or_else(|| detect_events(rows).iter().find(|event| event.kind == EventKind::Liftoff).map(|event| event.timestamp))
+        .or_else(|| rows.first().map(|rd| rd.timestamp))
+        .unwrap_or(0)
+}
+
+// A further log shown alongside the main flight for comparison.
+pub struct Flight {
+    pub name: String,
+    pub rows: Vec<RocketData>,
+    pub derived: Vec<DerivedSample>,
+    pub altitude: Vec<AltitudeEstimate>,
+    pub timeline: StateTimeline,
+    pub liftoff: u32,
+}
+
+impl Flight {
+    pub fn new(name: &str, rows: Vec<RocketData>, mapping: &StateMapping) -> Result<Flight, String> {
+        if rows.is_empty() {
+            return Err(format!("{}: no rows", name));
+        }
+        let timeline = StateTimeline::from_log(&rows, mapping);
+        Ok(Flight {
+            name: name.to_string(),
+            derived: derive(&rows),
+            altitude: fuse_altitude(&rows),
+            liftoff: liftoff_timestamp(&rows, &timeline),
+            timeline,
+            rows,
+        })
+    }
+
+    pub fn set_mapping(&mut self, mapping: &StateMapping) {
+        self.timeline = StateTimeline::from_log(&self.rows, mapping);
+        self.liftoff = liftoff_timestamp(&self.rows, &self.timeline);
+    }
+
+    // Row at `mission_ms` after liftoff, held at the first and last rows outside the log.
+    pub fn row_at(&self, mission_ms: i64) -> usize {
+        let timestamp = self.liftoff as i64 + mission_ms;
+        self.rows.partition_point(|rd| (rd.timestamp as i64) < timestamp).min(self.rows.len() - 1)
+    }
+
+    // Height above this flight's own pad, so flights from different fields or days line up.
+    pub fn height(&self, row: usize) -> f64 {
+        self.altitude[row].altitude - self.altitude[0].altitude
+    }
+
+    // Shift in seconds taking this flight's `flight_times` onto the time axis of a flight that lifts off
+    // `liftoff_seconds` after its first row.
+    pub fn time_shift(&self, liftoff_seconds: f32) -> f32 {
+        liftoff_seconds - self.liftoff.saturating_sub(self.rows[0].timestamp) as f32 / 1000.0
+    }
+}
@@ -0,0 +1,114 @@
+use nalgebra_glm as glm;
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlTexture, WebGlVertexArrayObject};
+
+use crate::flights::{flight_color, Flight, SIMULATION_COLOR};
+use crate::load_model::{make_box, segment};
+use crate::openrocket::Simulation;
+use crate::rocket_data::RocketData;
+use crate::webgl::{make_texture, make_vao_vert_norm_uv, ShaderProgram};
+
+// Each compared flight flies in its own lane this far apart along x, the main flight keeps the origin and
+// a simulation takes the first lane on the other side.
+const LANE_SPACING_METERS: f32 = 1.5;
+const GHOST_OPACITY: f32 = 0.6;
+const GHOST_TINT: f32 = 0.6;
+// The trail behind each rocket covers this much of the recent flight.
+const TRAIL_SECONDS: f64 = 3.0;
+const TRAIL_WIDTH: f32 = 0.08;
+
+// The mesh the main rocket is drawn with, reused for every ghost.
+pub struct RocketMesh<'a> {
+    pub vao: &'a WebGlVertexArrayObject,
+    pub vertex_count: i32,
+    pub texture: Option<&'a WebGlTexture>,
+}
+
+// Further flights and a simulation replayed next to the main flight, aligned at liftoff, each drawn as a
+// tinted see-through rocket with a trail in its chart colour.
+pub struct Ghosts {
+    pub flights: Vec<Flight>,
+    pub simulation: Option<Simulation>,
+    trail_vao: WebGlVertexArrayObject,
+    trail_vertex_count: i32,
+    white: Option<WebGlTexture>,
+}
+
+impl Ghosts {
+    pub fn new(context: &WebGl2RenderingContext, program: &ShaderProgram) -> Result<Ghosts, JsValue> {
+        let half = TRAIL_WIDTH / 2.0;
+        let (verts, norms, uvs) = make_box(&glm::vec3(-half, -half, 0.0), &glm::vec3(half, half, 1.0), [0.5, 0.5]);
+        let (trail_vao, trail_vertex_count) = make_vao_vert_norm_uv(context, program, &verts, &norms, &uvs)?;
+        let white = make_texture(context, &[255, 255, 255], 1, 1);
+        Ok(Ghosts { flights: Vec::new(), simulation: None, trail_vao, trail_vertex_count, white })
+    }
+
+    // Draws with the rocket program already in use. `mission_ms` is the main flight's time since liftoff,
+    // `trail` the heights its trail runs between and `pad_altitude` the scene height of the pad.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(&self, ctx: &WebGl2RenderingContext, program: &ShaderProgram, rocket: &RocketMesh, view_projection: &glm::Mat4,
+                  mission_ms: i64, trail: (f32, f32), pad_altitude: f32) {
+        if self.flights.is_empty() && self.simulation.is_none() {
+            return;
+        }
+        program.set_mat4(ctx, "light_mvp", &glm::identity());
+        program.set_f32(ctx, "receive_shadow", 0.0);
+
+        let trail_ms = (TRAIL_SECONDS * 1000.0) as i64;
+        let mut trails = vec![(0.0, trail, flight_color(0))];
+        // lane, height, roll and colour of each ghost
+        let mut rockets = Vec::new();
+        for (i, flight) in self.flights.iter().enumerate() {
+            let lane = LANE_SPACING_METERS * (i + 1) as f32;
+            let row = flight.row_at(mission_ms);
+            let z = pad_altitude + flight.height(row) as f32;
+            let tail = pad_altitude + flight.height(flight.row_at(mission_ms - trail_ms)) as f32;
+            let rd = &flight.rows[row];
+            trails.push((lane, (tail, z), flight_color(i + 1)));
+            rockets.push((lane, z, rd.mx.atan2(rd.mz) as f32, flight_color(i + 1)));
+        }
+        if let Some(simulation) = &self.simulation {
+            let time = simulation.liftoff() + mission_ms as f64 / 1000.0;
+            let z = pad_altitude + simulation.altitude_at(time) as f32;
+            let tail = pad_altitude + simulation.altitude_at(time - TRAIL_SECONDS) as f32;
+            trails.push((-LANE_SPACING_METERS, (tail, z), SIMULATION_COLOR));
+            rockets.push((-LANE_SPACING_METERS, z, 0.0, SIMULATION_COLOR));
+        }
+
+        ctx.bind_vertex_array(Some(rocket.vao));
+        program.set_texture(ctx, "tex", rocket.texture, 0);
+        program.set_f32(ctx, "opacity", GHOST_OPACITY);
+        for (lane, z, roll, color) in rockets {
+            let rotate = glm::rotate(&glm::identity(), roll, &glm::vec3(0.0, 0.0, 1.0));
+            let model = glm::translate(&glm::identity(), &glm::vec3(lane, 0.0, z))
+                * glm::scale(&glm::identity(), &glm::vec3(0.1, 0.1, 0.1))
+                * rotate;
+            program.set_vec4(ctx, "tint", &tint(color, GHOST_TINT));
+            program.set_mat4(ctx, "mvp", &(view_projection * model));
+            program.set_mat4(ctx, "rotate", &rotate);
+            ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, rocket.vertex_count);
+        }
+
+        ctx.bind_vertex_array(Some(&self.trail_vao));
+        program.set_texture(ctx, "tex", self.white.as_ref(), 0);
+        program.set_f32(ctx, "opacity", 1.0);
+        program.set_mat4(ctx, "rotate", &glm::identity());
+        for (lane, (tail, head), color) in trails {
+            let model = segment(&glm::vec3(lane, 0.0, tail), &glm::vec3(lane, 0.0, head));
+            program.set_vec4(ctx, "tint", &tint(color, 1.0));
+            program.set_mat4(ctx, "mvp", &(view_projection * model));
+            ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, self.trail_vertex_count);
+        }
+        program.set_vec4(ctx, "tint", &glm::vec4(0.0, 0.0, 0.0, 0.0));
+    }
+}
+
+// First row of the main flight's trail ending at `row`.
+pub fn trail_start_row(rows: &[RocketData], row: usize) -> usize {
+    let start = rows[row].timestamp.saturating_sub((TRAIL_SECONDS * 1000.0) as u32);
+    rows.partition_point(|rd| rd.timestamp < start).min(row)
+}
+
+fn tint((r, g, b): (u8, u8, u8), amount: f32) -> glm::Vec4 {
+    glm::vec4(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, amount)
+}
@@ -0,0 +1,99 @@
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, Document, HtmlCanvasElement};
+
+use crate::flight_state::StateTimeline;
+use crate::derived::DerivedSample;
+use crate::kalman::AltitudeEstimate;
+use crate::plot_graph::acceleration_magnitude;
+use crate::rocket_data::RocketData;
+use crate::units::{DisplayUnits, Unit};
+
+// Panel layout in css pixels from the top left of the overlay.
+const PANEL_X: f64 = 12.0;
+const PANEL_Y: f64 = 12.0;
+const PANEL_WIDTH: f64 = 260.0;
+const PANEL_PADDING: f64 = 10.0;
+const LINE_HEIGHT: f64 = 20.0;
+const VALUE_X: f64 = 120.0;
+const FONT: &str = "14px monospace";
+
+// Text readouts drawn on the "hud" overlay canvas above the 3d view.
+pub struct Hud {
+    canvas: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+}
+
+impl Hud {
+    pub fn new(document: &Document) -> Result<Hud, JsValue> {
+        let canvas = document
+            .get_element_by_id("hud")
+            .ok_or_else(|| JsValue::from_str("missing hud canvas"))?
+            .dyn_into::<HtmlCanvasElement>()?;
+        let context = canvas
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("no 2d context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+
+        Ok(Hud { canvas, context })
+    }
+
+    pub fn draw(&self, rows: &[RocketData], derived: &[DerivedSample], altitude: &[AltitudeEstimate], timeline: &StateTimeline, units: &DisplayUnits, index: usize) -> Result<(), JsValue> {
+        let (width, height) = (self.canvas.client_width().max(1) as u32, self.canvas.client_height().max(1) as u32);
+        if self.canvas.width() != width || self.canvas.height() != height {
+            self.canvas.set_width(width);
+            self.canvas.set_height(height);
+        }
+        self.context.clear_rect(0.0, 0.0, width as f64, height as f64);
+
+        let index = match rows.len() {
+            0 => return Ok(()),
+            len => index.min(len - 1),
+        };
+        // mission time counts from the first in-flight state
+        let liftoff_timestamp = timeline.liftoff_timestamp().unwrap_or(rows[0].timestamp);
+        let state = timeline.phase_at_row(index).map(|phase| phase.state.to_string()).unwrap_or_default();
+        let lines = hud_lines(&rows[index], &derived[index], &altitude[index], &state, liftoff_timestamp, units);
+
+        let panel_height = PANEL_PADDING * 2.0 + LINE_HEIGHT * lines.len() as f64;
+        self.context.set_fill_style_str("rgba(0, 0, 0, 0.55)");
+        self.context.fill_rect(PANEL_X, PANEL_Y, PANEL_WIDTH, panel_height);
+
+        self.context.set_font(FONT);
+        self.context.set_text_baseline("top");
+        for (i, (label, value)) in lines.iter().enumerate() {
+            let y = PANEL_Y + PANEL_PADDING + LINE_HEIGHT * i as f64;
+            self.context.set_fill_style_str("#9ab");
+            self.context.fill_text(label, PANEL_X + PANEL_PADDING, y)?;
+            self.context.set_fill_style_str("#fff");
+            self.context.fill_text(value, PANEL_X + VALUE_X, y)?;
+        }
+        Ok(())
+    }
+}
+
+fn mission_time(timestamp: u32, liftoff_timestamp: u32) -> String {
+    let sign = if timestamp >= liftoff_timestamp { '+' } else { '-' };
+    let ms = timestamp.abs_diff(liftoff_timestamp);
+    format!("T{}{:02}:{:02}.{:02}", sign, ms / 60_000, ms / 1000 % 60, ms / 10 % 100)
+}
+
+// The readouts in display order, as (label, value).
+pub fn hud_lines(rd: &RocketData, derived: &DerivedSample, altitude: &AltitudeEstimate, state: &str, liftoff_timestamp: u32, units: &DisplayUnits) -> Vec<(&'static str, String)> {
+    let length = units.unit_for(Unit::Meter);
+    let speed = units.unit_for(Unit::MeterPerSecond);
+    vec![
+        ("Mission time", mission_time(rd.timestamp, liftoff_timestamp)),
+        ("State", state.to_string()),
+        ("Altitude", format!("{:.1} \u{b1} {:.1} {}", units.value(altitude.altitude, Unit::Meter),
+                             units.value(2.0 * altitude.altitude_sigma(), Unit::Meter), length.symbol())),
+        ("Baro alt", units.format(rd.barometer_altitude, Unit::Meter, 1)),
+        ("GPS alt", units.format(rd.altitude, Unit::Meter, 1)),
+        ("Vert speed", format!("{:+.1} {}", units.value(derived.vertical_velocity, Unit::MeterPerSecond), speed.symbol())),
+        ("Mach", format!("{:.2}", derived.mach)),
+        ("Dyn pressure", units.format(derived.dynamic_pressure, Unit::Pascal, 1)),
+        ("Accel", units.format(acceleration_magnitude(rd) as f64, Unit::StandardGravity, 2)),
+        ("Satellites", format!("{} ({})", rd.satellite_count, if rd.position_lock != 0 { "lock" } else { "no lock" })),
+        ("Temperature", units.format(rd.temperature, Unit::Celsius, 1)),
+        ("Pressure", units.format(rd.pressure, Unit::Hectopascal, 1)),
+    ]
+}
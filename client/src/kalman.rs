@@ -0,0 +1,251 @@
+use nalgebra_glm as glm;
+
+use crate::derived::sample_period_seconds;
+use crate::flight_events::detect_liftoff;
+use crate::rocket_data::RocketData;
+use crate::validation::accel_saturated;
+
+const GRAVITY: f64 = 9.80665;
+
+// Standard deviations of the process and measurement noise.
+const ACCEL_SIGMA: f64 = 2.0;               // m/s^2, vertical acceleration input
+const SATURATED_ACCEL_SIGMA: f64 = 50.0;    // m/s^2, while any axis is clipped
+const BIAS_DRIFT_SIGMA: f64 = 0.3;          // m/s^2 per sqrt(s), how fast the accelerometer bias can wander
+const BARO_SIGMA: f64 = 1.5;                // m
+// Barometer readings through the transonic region are dominated by shock pressure, not altitude.
+const TRANSONIC_BARO_SIGMA: f64 = 10.0;     // m
+const TRANSONIC_MACH: (f64, f64) = (0.8, 1.3);
+const SPEED_OF_SOUND: f64 = 340.0;          // m/s, only used to decide when the barometer is transonic
+const GPS_SIGMA: f64 = 10.0;                // m, with GPS_REFERENCE_SATELLITES in view
+const GPS_REFERENCE_SATELLITES: f64 = 8.0;
+const GPS_MIN_SATELLITES: u32 = 4;
+// GPS updates further than this many standard deviations from the prediction are dropped, the receiver
+// reports stale fixes for tens of seconds after losing track during boost.
+const GPS_GATE_SIGMAS: f64 = 5.0;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AltitudeEstimate {
+    pub altitude: f64,          // m, same datum as barometer_altitude
+    pub velocity: f64,          // m/s, positive up
+    pub altitude_variance: f64, // m^2
+    pub velocity_variance: f64, // m^2/s^2
+}
+
+impl AltitudeEstimate {
+    pub fn altitude_sigma(&self) -> f64 {
+        self.altitude_variance.sqrt()
+    }
+}
+
+// Constant acceleration model on [altitude, velocity, acceleration bias] driven by the measured vertical
+// acceleration. The bias state soaks up what the accelerometer gets wrong for long stretches: attitude
+// drift once the rocket tumbles after apogee, and the clipped readings during boost.
+pub struct AltitudeFilter {
+    x: glm::TVec3<f64>,
+    p: glm::TMat3<f64>,
+}
+
+impl AltitudeFilter {
+    pub fn new(altitude: f64, altitude_variance: f64) -> AltitudeFilter {
+        AltitudeFilter {
+            x: glm::vec3(altitude, 0.0, 0.0),
+            p: glm::mat3(
+                altitude_variance, 0.0, 0.0,
+                0.0, 1.0, 0.0,
+                0.0, 0.0, 1.0,
+            ),
+        }
+    }
+
+    pub fn predict(&mut self, dt: f64, acceleration: f64, acceleration_sigma: f64) {
+        let f = glm::mat3(
+            1.0, dt, -0.5 * dt * dt,
+            0.0, 1.0, -dt,
+            0.0, 0.0, 1.0,
+        );
+        let g = glm::vec3(0.5 * dt * dt, dt, 0.0);
+        self.x = f * self.x + g * acceleration;
+
+        let q = acceleration_sigma * acceleration_sigma;
+        let mut noise = g * g.transpose() * q;
+        noise[(2, 2)] = BIAS_DRIFT_SIGMA * BIAS_DRIFT_SIGMA * dt;
+        self.p = f * self.p * f.transpose() + noise;
+    }
+
+    // Applies an altitude measurement, unless it is more than `gate` standard deviations of the innovation
+    // away from the prediction. Returns whether it was applied.
+    pub fn update_altitude(&mut self, altitude: f64, sigma: f64, gate: Option<f64>) -> bool {
+        let innovation = altitude - self.x.x;
+        let s = self.p[(0, 0)] + sigma * sigma;
+        if let Some(gate) = gate {
+            if innovation * innovation > gate * gate * s {
+                return false;
+            }
+        }
+
+        let k = self.p.column(0) / s;
+        self.x += k * innovation;
+        self.p -= k * self.p.row(0);
+        true
+    }
+
+    pub fn estimate(&self) -> AltitudeEstimate {
+        AltitudeEstimate {
+            altitude: self.x.x,
+            velocity: self.x.y,
+            altitude_variance: self.p[(0, 0)],
+            velocity_variance: self.p[(1, 1)],
+        }
+    }
+}
+
+// Vertical acceleration in m/s^2 for every row, from the accelerometer rotated into the world frame by an
+// attitude integrated from the gyros. The attitude starts level with gravity as measured on the pad, and
+// the gyro bias is the pad average.
+pub fn vertical_acceleration(rows: &[RocketData]) -> Vec<f64> {
+    let pad = &rows[..detect_liftoff(rows).unwrap_or(rows.len()).max(1).min(rows.len())];
+    let mean = |f: fn(&RocketData) -> f64| pad.iter().map(f).sum::<f64>() / pad.len().max(1) as f64;
+    let gravity = glm::vec3(mean(|rd| rd.ax), mean(|rd| rd.ay), mean(|rd| rd.az));
+    let gyro_bias = glm::vec3(mean(|rd| rd.gx), mean(|rd| rd.gy), mean(|rd| rd.gz));
+
+    let mut attitude = if glm::length(&gravity) > 0.0 {
+        glm::quat_rotation(&glm::normalize(&gravity), &glm::vec3(0.0, 0.0, 1.0))
+    } else {
+        glm::quat_identity()
+    };
+
+    let mut previous = rows.first().map(|rd| rd.timestamp).unwrap_or(0);
+    rows.iter().map(|rd| {
+        // a row stamped before the one it follows adds no rotation
+        let dt = rd.timestamp.saturating_sub(previous) as f64 / 1000.0;
+        previous = previous.max(rd.timestamp);
+
+        let rate = (glm::vec3(rd.gx, rd.gy, rd.gz) - gyro_bias) * std::f64::consts::PI / 180.0;
+        let angle = glm::length(&rate) * dt;
+        if angle > 1e-9 {
+            attitude = glm::quat_normalize(&(attitude * glm::quat_angle_axis(angle, &glm::normalize(&rate))));
+        }
+
+        let specific_force = glm::quat_rotate_vec3(&attitude, &glm::vec3(rd.ax, rd.ay, rd.az));
+        (specific_force.z - 1.0) * GRAVITY
+    }).collect()
+}
+
+// Fuses barometer, GPS and accelerometer into one altitude per row. GPS is shifted onto the barometer datum
+// by the offset between the two on the pad.
+pub fn fuse_altitude(rows: &[RocketData]) -> Vec<AltitudeEstimate> {
+    if rows.is_empty() {
+        return Vec::new();
+    }
+    let acceleration = vertical_acceleration(rows);
+    let period = sample_period_seconds(rows);
+
+    let pad_fix = rows.iter().take_while(|rd| !accel_saturated(rd)).filter(|rd| rd.position_lock != 0).last();
+    let gps_offset = pad_fix.map(|rd| rd.barometer_altitude - rd.altitude);
+
+    let mut filter = AltitudeFilter::new(rows[0].barometer_altitude, BARO_SIGMA * BARO_SIGMA);
+    let mut previous: Option<&RocketData> = None;
+
+    rows.iter().zip(acceleration.iter()).map(|(rd, a)| {
+        let dt = match previous {
+            // a row stamped at or before the previous one can't advance the filter, it repeats the estimate
+            Some(p) if rd.timestamp <= p.timestamp => return filter.estimate(),
+            Some(p) => (rd.timestamp - p.timestamp) as f64 / 1000.0,
+            None => period,
+        };
+        filter.predict(dt, *a, if accel_saturated(rd) { SATURATED_ACCEL_SIGMA } else { ACCEL_SIGMA });
+
+        let mach = filter.estimate().velocity.abs() / SPEED_OF_SOUND;
+        let transonic = mach > TRANSONIC_MACH.0 && mach < TRANSONIC_MACH.1;
+        filter.update_altitude(rd.barometer_altitude, if transonic { TRANSONIC_BARO_SIGMA } else { BARO_SIGMA }, None);
+
+        // only fresh fixes, repeating a held value would count it many times over
+        let fresh = previous.map(|p| p.altitude != rd.altitude).unwrap_or(true);
+        if let Some(offset) = gps_offset {
+            if fresh && rd.position_lock != 0 && rd.satellite_count >= GPS_MIN_SATELLITES {
+                let sigma = GPS_SIGMA * (GPS_REFERENCE_SATELLITES / rd.satellite_count as f64).sqrt();
+                filter.update_altitude(rd.altitude + offset, sigma, Some(GPS_GATE_SIGMAS));
+            }
+        }
+
+        previous = Some(rd);
+        filter.estimate()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csvreader::get_rocket_data;
+
+    // Barometer readings of a 50 m/s climb from 300 m every 10 ms, with up to 2 sigma of uniform noise.
+    fn noisy_climb(seconds: f64) -> Vec<(f64, f64)> {
+        let mut state = 0x1234_5678u32;
+        (0..(seconds * 100.0) as usize).map(|i| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let noise = (state as f64 / u32::MAX as f64 * 2.0 - 1.0) * 2.0 * BARO_SIGMA;
+            let truth = 300.0 + 50.0 * i as f64 / 100.0;
+            (truth, truth + noise)
+        }).collect()
+    }
+
+    fn climbed_filter(seconds: f64) -> (AltitudeFilter, f64) {
+        let mut filter = AltitudeFilter::new(300.0, BARO_SIGMA * BARO_SIGMA);
+        let mut truth = 0.0;
+        for (altitude, reading) in noisy_climb(seconds) {
+            filter.predict(0.01, 0.0, ACCEL_SIGMA);
+            filter.update_altitude(reading, BARO_SIGMA, None);
+            truth = altitude;
+        }
+        (filter, truth)
+    }
+
+    #[test]
+    fn converges_on_a_constant_velocity_climb() {
+        let (filter, truth) = climbed_filter(20.0);
+        let estimate = filter.estimate();
+        assert!((estimate.velocity - 50.0).abs() < 1.0, "velocity {}", estimate.velocity);
+        assert!((estimate.altitude - truth).abs() < BARO_SIGMA, "altitude {} for {}", estimate.altitude, truth);
+        assert!(estimate.altitude_sigma() < BARO_SIGMA);
+    }
+
+    #[test]
+    fn gate_rejects_a_gps_outlier() {
+        let (mut filter, truth) = climbed_filter(10.0);
+        let before = filter.estimate();
+        assert!(!filter.update_altitude(truth + 200.0, GPS_SIGMA, Some(GPS_GATE_SIGMAS)));
+        assert_eq!(filter.estimate().altitude, before.altitude);
+        assert_eq!(filter.estimate().altitude_variance, before.altitude_variance);
+        assert!(filter.update_altitude(truth + 5.0, GPS_SIGMA, Some(GPS_GATE_SIGMAS)));
+    }
+
+    #[test]
+    fn sigma_shrinks_with_each_update() {
+        let mut filter = AltitudeFilter::new(0.0, 100.0);
+        let mut sigma = filter.estimate().altitude_sigma();
+        for _ in 0..10 {
+            filter.update_altitude(0.0, BARO_SIGMA, None);
+            let updated = filter.estimate().altitude_sigma();
+            assert!(updated < sigma, "{} after {}", updated, sigma);
+            sigma = updated;
+        }
+        assert!(sigma < BARO_SIGMA);
+    }
+
+    #[test]
+    fn skips_rows_stamped_before_the_previous() {
+        let mut rows = get_rocket_data().unwrap();
+        // one row in the climb steps back 50 ms
+        rows[2000].timestamp = rows[1999].timestamp - 50;
+
+        let acceleration = vertical_acceleration(&rows);
+        let estimates = fuse_altitude(&rows);
+        assert_eq!(estimates.len(), rows.len());
+        assert!(acceleration.iter().all(|a| a.is_finite()));
+        assert!(estimates.iter().all(|e| e.altitude.is_finite() && e.velocity.is_finite()));
+        assert_eq!(estimates[2000].altitude, estimates[1999].altitude);
+        assert!((estimates[2001].altitude - rows[2001].barometer_altitude).abs() < 50.0);
+    }
+}
@@ -0,0 +1,210 @@
+use nalgebra_glm as glm;
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlTexture, WebGlVertexArrayObject};
+
+use crate::load_model::{load_file, make_box, segment, transform_mesh, Mesh};
+use crate::terrain::{enu_offset, parse_geotiff, parse_heightmap_png, terrain_mesh, Heightmap};
+use crate::webgl::{make_texture, make_vao_vert_norm_uv, ShaderProgram};
+
+// Hardware sizes in metres. The rail stands against the first stage, which is 0.19 m in radius.
+const RAIL_WIDTH: f32 = 0.05;
+const RAIL_OFFSET: f32 = 0.24;
+const BLAST_PLATE_SIZE: f32 = 1.5;
+const BLAST_PLATE_THICKNESS: f32 = 0.05;
+const STRUT_WIDTH: f32 = 0.04;
+const STATION_MAST_HEIGHT: f32 = 3.0;
+const STATION_MAST_WIDTH: f32 = 0.06;
+const STATION_SHELTER: [f32; 3] = [1.6, 1.0, 1.0];
+const STATION_ANTENNA: [f32; 3] = [0.15, 0.9, 0.15];
+
+// Texel centres in the palette texture, see `palette`.
+const STEEL_UV: [f32; 2] = [0.1, 0.5];
+const CONCRETE_UV: [f32; 2] = [0.3, 0.5];
+const WHITE_UV: [f32; 2] = [0.5, 0.5];
+const RED_UV: [f32; 2] = [0.7, 0.5];
+const GROUND_UV: [f32; 2] = [0.9, 0.5];
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct GeoPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// A local elevation model: an uncompressed GeoTIFF in latitude/longitude, or a grey heightmap PNG, which
+// has no position of its own and is centred on the site at `size_meters` across.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TerrainConfig {
+    pub path: String,
+    #[serde(default)]
+    pub size_meters: f64,
+    #[serde(default)]
+    pub min_height: f64,
+    #[serde(default)]
+    pub max_height: f64,
+}
+
+// Launch site description, given to the page as JSON with every field optional, e.g.
+// {"site": {"latitude": 41.4877, "longitude": -89.5062}, "rail_length": 6.1,
+//  "ground_station": {"latitude": 41.4881, "longitude": -89.5070}, "terrain": {"path": "dem.tif"}}
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LaunchSiteConfig {
+    // pad position and the origin of the scene's east-north-up frame, the log's first GPS fix if absent
+    pub site: Option<GeoPosition>,
+    pub rail_length: f32, // m
+    pub ground_station: Option<GeoPosition>,
+    pub terrain: Option<TerrainConfig>,
+}
+
+impl Default for LaunchSiteConfig {
+    fn default() -> LaunchSiteConfig {
+        LaunchSiteConfig {
+            site: None,
+            rail_length: 6.1,
+            ground_station: None,
+            terrain: None,
+        }
+    }
+}
+
+impl LaunchSiteConfig {
+    pub fn parse(json: &str) -> Result<LaunchSiteConfig, String> {
+        serde_json::from_str(json).map_err(|e| format!("launch site: {}", e))
+    }
+}
+
+// Fetches and parses the configured elevation model, positioned around `origin`.
+pub async fn load_terrain(config: &LaunchSiteConfig, origin: &GeoPosition) -> Result<Option<Heightmap>, JsValue> {
+    let terrain = match &config.terrain {
+        Some(terrain) => terrain,
+        None => return Ok(None),
+    };
+    let bytes = load_file(&terrain.path).await?;
+    let path = terrain.path.to_lowercase();
+    let map = if path.ends_with(".png") {
+        parse_heightmap_png(&bytes, terrain.size_meters, terrain.min_height, terrain.max_height)
+    } else if path.ends_with(".tif") || path.ends_with(".tiff") {
+        parse_geotiff(&bytes, origin.latitude, origin.longitude)
+    } else {
+        Err("expected a .tif/.tiff GeoTIFF or a .png heightmap".to_string())
+    };
+    map.map(Some).map_err(|e| JsValue::from_str(&format!("{}: {}", terrain.path, e)))
+}
+
+// Pad hardware, ground station and terrain, all meshes built in scene space around the origin.
+pub struct LaunchSite {
+    objects: Vec<(WebGlVertexArrayObject, i32)>,
+    texture: Option<WebGlTexture>,
+}
+
+impl LaunchSite {
+    // `pad_altitude` is where the rocket stands in the scene's altitude datum. Terrain heights are shifted
+    // to meet it at the pad, since DEM, GPS and barometer rarely agree on their zero.
+    pub fn new(context: &WebGl2RenderingContext, program: &ShaderProgram, config: &LaunchSiteConfig, origin: GeoPosition, terrain: Option<Heightmap>, pad_altitude: f32) -> Result<LaunchSite, JsValue> {
+        let mut meshes = launch_rail(config.rail_length, pad_altitude);
+
+        let ground = |map: &Option<Heightmap>, east: f64, north: f64| map.as_ref().and_then(|map| map.sample(east, north)).unwrap_or(pad_altitude);
+        let mut terrain = terrain;
+        if let Some(map) = terrain.as_mut() {
+            let pad = map.sample(0.0, 0.0).ok_or_else(|| JsValue::from_str("terrain does not cover the launch site"))?;
+            map.shift(pad_altitude - pad);
+        }
+        if let Some(station) = &config.ground_station {
+            let (east, north) = enu_offset(origin.latitude, origin.longitude, station.latitude, station.longitude);
+            let base = glm::vec3(east as f32, north as f32, ground(&terrain, east, north));
+            meshes.extend(ground_station(&base));
+        }
+        if let Some(map) = &terrain {
+            meshes.push(terrain_mesh(map, GROUND_UV));
+        }
+
+        let mut objects = Vec::new();
+        for (verts, norms, uvs) in meshes {
+            objects.push(make_vao_vert_norm_uv(context, program, &verts, &norms, &uvs)?);
+        }
+
+        Ok(LaunchSite {
+            objects,
+            texture: make_texture(context, &palette(), 5, 1),
+        })
+    }
+
+    // Draws with the rocket program already in use. The meshes are in scene space, so the model matrix is
+    // the identity.
+    pub fn render(&self, ctx: &WebGl2RenderingContext, program: &ShaderProgram, view_projection: &glm::Mat4, light_view_projection: &glm::Mat4) {
+        program.set_texture(ctx, "tex", self.texture.as_ref(), 0);
+        program.set_mat4(ctx, "mvp", view_projection);
+        program.set_mat4(ctx, "rotate", &glm::identity());
+        program.set_mat4(ctx, "light_mvp", light_view_projection);
+        program.set_f32(ctx, "receive_shadow", 1.0);
+        program.set_f32(ctx, "opacity", 1.0);
+        for (vao, vertex_count) in &self.objects {
+            ctx.bind_vertex_array(Some(vao));
+            ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, *vertex_count);
+        }
+    }
+}
+
+// BGR texels, the order the rocket shader expects from bmp textures.
+fn palette() -> Vec<u8> {
+    vec![
+        150, 150, 155, // steel
+        105, 110, 110, // concrete
+        235, 235, 235, // white
+        40, 40, 200,   // red
+        70, 125, 95,   // ground
+    ]
+}
+
+// Blast plate, the rail on the +x side of the rocket and a strut bracing it from behind.
+fn launch_rail(rail_length: f32, pad_altitude: f32) -> Vec<Mesh> {
+    let half_plate = BLAST_PLATE_SIZE / 2.0;
+    let plate = make_box(
+        &glm::vec3(-half_plate, -half_plate, pad_altitude - BLAST_PLATE_THICKNESS),
+        &glm::vec3(half_plate, half_plate, pad_altitude),
+        CONCRETE_UV,
+    );
+    let rail = make_box(
+        &glm::vec3(RAIL_OFFSET, -RAIL_WIDTH / 2.0, pad_altitude),
+        &glm::vec3(RAIL_OFFSET + RAIL_WIDTH, RAIL_WIDTH / 2.0, pad_altitude + rail_length),
+        STEEL_UV,
+    );
+
+    let unit = make_box(&glm::vec3(-0.5, -0.5, 0.0), &glm::vec3(0.5, 0.5, 1.0), STEEL_UV);
+    let brace_top = glm::vec3(RAIL_OFFSET + RAIL_WIDTH, 0.0, pad_altitude + rail_length * 0.6);
+    let brace_foot = glm::vec3(RAIL_OFFSET + RAIL_WIDTH + rail_length * 0.35, 0.0, pad_altitude);
+    let strut = transform_mesh(unit, &(segment(&brace_foot, &brace_top) * glm::scale(&glm::identity(), &glm::vec3(STRUT_WIDTH, STRUT_WIDTH, 1.0))));
+
+    vec![plate, rail, strut]
+}
+
+// Shelter with a mast and a crossed yagi on top, standing at `base`.
+fn ground_station(base: &glm::Vec3) -> Vec<Mesh> {
+    let place = glm::translate(&glm::identity(), base);
+    let shelter = make_box(
+        &glm::vec3(-STATION_SHELTER[0] / 2.0, -STATION_SHELTER[1] / 2.0, 0.0),
+        &glm::vec3(STATION_SHELTER[0] / 2.0, STATION_SHELTER[1] / 2.0, STATION_SHELTER[2]),
+        WHITE_UV,
+    );
+    let mast = make_box(
+        &glm::vec3(-STATION_MAST_WIDTH / 2.0, -STATION_MAST_WIDTH / 2.0, 0.0),
+        &glm::vec3(STATION_MAST_WIDTH / 2.0, STATION_MAST_WIDTH / 2.0, STATION_MAST_HEIGHT),
+        STEEL_UV,
+    );
+    let antenna = make_box(
+        &glm::vec3(-STATION_ANTENNA[0] / 2.0, -STATION_ANTENNA[1] / 2.0, STATION_MAST_HEIGHT),
+        &glm::vec3(STATION_ANTENNA[0] / 2.0, STATION_ANTENNA[1] / 2.0, STATION_MAST_HEIGHT + STATION_ANTENNA[2]),
+        RED_UV,
+    );
+    let crossed = transform_mesh(antenna.clone(), &glm::rotate(&glm::identity(), std::f32::consts::FRAC_PI_2, &glm::vec3(0.0, 0.0, 1.0)));
+
+    // the mast stands beside the shelter rather than through it
+    let mast_offset = glm::translate(&glm::identity(), &glm::vec3(STATION_SHELTER[0] / 2.0 + 0.3, 0.0, 0.0));
+    vec![
+        transform_mesh(shelter, &place),
+        transform_mesh(mast, &(place * mast_offset)),
+        transform_mesh(antenna, &(place * mast_offset)),
+        transform_mesh(crossed, &(place * mast_offset)),
+    ]
+}
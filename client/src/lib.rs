@@ -1,91 +1,126 @@
 #![allow(unused_variables)]
 
+use std::ptr::{addr_of, addr_of_mut};
+
 use js_sys::JsString;
 use nalgebra_glm as glm;
 use wasm_bindgen::prelude::*;
 use web_sys::*;
 
+use earth::*;
 use load_model::*;
 use mvpmatrix::get_model;
+use plot_graph::StripCharts;
+use hud::Hud;
+use map_panel::MapPanel;
+use wind_arrows::WindArrows;
+use ghosts::{Ghosts, RocketMesh};
+use capture::{OffscreenTarget, Recording};
+use flights::Flight;
+use rocket_parts::{airbrake_parts, RocketParts};
+use parachutes::{default_parachutes, Parachutes};
+use particles::*;
+use launch_site::{load_terrain, GeoPosition, LaunchSite, LaunchSiteConfig};
+use flight_state::{StateMapping, StateTimeline};
+use units::DisplayUnits;
+use shaders::*;
+use shadow::*;
+use sky::*;
 use webgl::*;
 
 use crate::rocket_data::RocketData;
 
-mod csvreader;
+pub mod csvreader;
 mod mvpmatrix;
-mod rocket_data;
+pub mod rocket_data;
 mod webgl;
 mod load_model;
-
-static VERT_SOURCE: &str =
-    r##"#version 300 es
-
-in vec3 position;
-in vec3 normal;
-in vec2 uv;
-
-out vec3 frag_normal;
-out vec2 frag_uv;
-uniform mat4 mvp;
-uniform mat4 rotate;
-
-void main() {
-    frag_normal = (rotate * vec4(normal, 0)).xyz;
-    frag_uv = uv;
-    gl_Position = mvp * vec4(position, 1);
-}
-"##;
-
-static FRAG_SOURCE: &str =
-r##"#version 300 es
-
-precision highp float;
-in vec3 frag_normal;
-in vec2 frag_uv;
-
-out vec4 outColor;
-vec3 light = vec3(0.7,0.7,0);
-
-uniform sampler2D tex;
-uniform float opacity;
-
-void main() {
-    float intensity = max(dot(light, frag_normal), 0.0) + 0.7;
-    vec4 color = texture(tex, frag_uv);
-    outColor = vec4(color.zyx * intensity, opacity);
-}
-"##;
-
+mod shadow;
+mod earth;
+mod sky;
+mod shaders;
+pub mod plot_graph;
+mod hud;
+mod map_panel;
+mod wind_arrows;
+mod capture;
+mod ghosts;
+mod rocket_parts;
+mod parachutes;
+mod particles;
+mod launch_site;
+pub mod terrain;
+pub mod recovery;
+pub mod wind;
+pub mod flights;
+pub mod openrocket;
+pub mod flight_state;
+pub mod flight_events;
+pub mod derived;
+pub mod kalman;
+pub mod validation;
+pub mod units;
+#[cfg(feature = "cli")]
+pub mod log_tools;
+
+const ROCKET_SHADER: ShaderFiles = ShaderFiles { vert: "rocket.vert.glsl", frag: "rocket.frag.glsl" };
 
 struct GlobalData {
     pub canvas: HtmlCanvasElement,
     pub ctx: WebGl2RenderingContext,
-    pub program: WebGlProgram,
+    pub program: ShaderProgram,
     pub rocket_vao: WebGlVertexArrayObject,
     pub rocket_tex: Option<WebGlTexture>,
+    pub rocket_bounds: (glm::Vec3, glm::Vec3),
+    pub rocket_parts: RocketParts,
+    pub parachutes: Parachutes,
+    pub particles: ParticleSystem,
+    pub launch_site: LaunchSite,
+    pub wind_arrows: WindArrows,
+    pub ghosts: Ghosts,
+    pub shadow_map: ShadowMap,
     pub planet_models: Vec<(WebGlVertexArrayObject, i32)>,
     pub planet_textures: Vec<Option<WebGlTexture>>,
-    pub tex_location: Option<WebGlUniformLocation>,
-    pub mvp_location: Option<WebGlUniformLocation>,
-    pub rotate_location: Option<WebGlUniformLocation>,
-    pub opacity_location: Option<WebGlUniformLocation>,
+    pub earth: Earth,
+    pub sky: Sky,
+    pub charts: StripCharts,
+    pub hud: Hud,
+    pub map: MapPanel,
+    pub mapping: StateMapping,
+    pub timeline: StateTimeline,
+    pub liftoff: u32,
+    pub derived: Vec<derived::DerivedSample>,
+    pub altitude: Vec<kalman::AltitudeEstimate>,
+    pub units: DisplayUnits,
     pub rocket_vertex_count: i32,
     pub frame_count: u64,
+    pub recording: Option<Recording>,
 }
 
 static mut GLOBAL_DATA: Option<GlobalData> = None;
 
 static mut ROCKET_DATA_VEC: Vec<RocketData> = Vec::<RocketData>::new();
+
+// The statics are only reached through these two. The wasm module is single threaded and no export calls
+// another, so the references handed out never overlap.
+fn global_data() -> Option<&'static mut GlobalData> {
+    unsafe { (*addr_of_mut!(GLOBAL_DATA)).as_mut() }
+}
+
+fn rocket_data() -> &'static [RocketData] {
+    unsafe { (*addr_of!(ROCKET_DATA_VEC)).as_slice() }
+}
+
 const ROCKET_DATA_TIMESTEP_SECONDS: f64 = 0.01;
 const START_TIME_SECONDS: f64 = 7.0;
 const TIME_SCALE: f64 = 1.0;
 const FRAMES_PER_SECOND: f64 = 60.0;
-
-async fn make_obj_vao(context: &WebGl2RenderingContext, program: &WebGlProgram, obj_path: &str) -> Result<(WebGlVertexArrayObject, i32), JsValue> {
-    let (vertices, normals, uvs) = load_mesh(obj_path).await?;
-
-    return make_vao_vert_norm_uv(context, program, &vertices, &normals, &uvs);
-}
+// the camera circles the rocket at this rate of display time
+const CAMERA_RADIANS_PER_SECOND: f64 = FRAMES_PER_SECOND / 1000.0;
+// the charts only move a pixel or so per frame, redrawing them every frame is wasted work
+const CHART_FRAME_INTERVAL: u64 = 4;
+// Direction towards the sun in scene space (z up), kept high enough that the rocket casts a visible shadow.
+const SUN_DIRECTION: [f32; 3] = [0.5, 0.5, 0.7];
 
 async fn make_texture_bmp(context: &WebGl2RenderingContext, bmp_path: &str) -> Result<Option<WebGlTexture>, JsValue> {
     let (texture_data, texture_width, texture_height) = load_bmp(bmp_path).await?;
@@ -96,7 +131,7 @@ async fn make_texture_bmp(context: &WebGl2RenderingContext, bmp_path: &str) -> R
 
 #[wasm_bindgen]
 pub async fn start(csv: String) -> Result<(), JsValue> {
-    unsafe {ROCKET_DATA_VEC = csvreader::get_csv_vec(csv.as_bytes()).unwrap()};
+    unsafe { *addr_of_mut!(ROCKET_DATA_VEC) = csvreader::get_csv_vec(csv.as_bytes()).unwrap() };
 
     let canvas = get_canvas().unwrap();
     let context = get_context(&canvas).unwrap();
@@ -105,28 +140,30 @@ pub async fn start(csv: String) -> Result<(), JsValue> {
     context.depth_func(WebGl2RenderingContext::LEQUAL);
     context.enable(WebGl2RenderingContext::BLEND);
     context.blend_func_separate(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA, WebGl2RenderingContext::ONE, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
-    let program = make_program(&context, VERT_SOURCE, FRAG_SOURCE)?;
-    context.use_program(Some(&program));
-
-    let (rocket_vao, rocket_vert_count) = make_obj_vao(&context, &program,
-                                  "Models/Ares_I_-_OBJ/Ares I/ares_I.obj").await?;
+    let program = load_shader_program(&context, &ROCKET_SHADER).await?;
+    program.use_program(&context);
+
+    let (rocket_verts, rocket_norms, rocket_uvs) = load_mesh("Models/Ares_I_-_OBJ/Ares I/ares_I.obj").await?;
+    let (rocket_vao, rocket_vert_count) = make_vao_vert_norm_uv(&context, &program, &rocket_verts, &rocket_norms, &rocket_uvs)?;
+    let rocket_parts = RocketParts::new(&context, &program, airbrake_parts())?;
+    // the shadow camera has to cover the flaps when they are open too
+    let rocket_bounds = match rocket_parts.bounds() {
+        Some((min, max)) => {
+            let (rocket_min, rocket_max) = mesh_bounds(&rocket_verts);
+            (glm::min2(&rocket_min, &min), glm::max2(&rocket_max, &max))
+        }
+        None => mesh_bounds(&rocket_verts),
+    };
 
     let (verts, norms, uvs) = make_plane();
-    let sphere = make_obj_vao(&context, &program, "Models/Earth/Earth_2K.obj").await?;
     let planet_models = vec![
         make_vao_vert_norm_uv(&context, &program, &verts, &norms, &uvs)?,
         make_vao_vert_norm_uv(&context, &program, &verts, &norms, &uvs)?,
         make_vao_vert_norm_uv(&context, &program, &verts, &norms, &uvs)?,
         make_vao_vert_norm_uv(&context, &program, &verts, &norms, &uvs)?,
-        sphere
     ];
 
 
-    let mvp_uniform_location = context.get_uniform_location(&program, "mvp");
-    let rotate_uniform_location = context.get_uniform_location(&program, "rotate");
-    let opacity_uniform_location = context.get_uniform_location(&program, "opacity");
-
-    let texture_uniform_location = context.get_uniform_location(&program, "tex");
     let rocket_tex = make_texture_bmp(&context,"Models/Ares_I_-_OBJ/Ares I/ares_I.bmp").await?;
 
     let planet_textures = vec![
@@ -134,55 +171,351 @@ pub async fn start(csv: String) -> Result<(), JsValue> {
         make_texture_bmp(&context,"Models/Earth/Textures/close1.bmp").await?,
         make_texture_bmp(&context,"Models/Earth/Textures/close2.bmp").await?,
         make_texture_bmp(&context,"Models/Earth/Textures/close3.bmp").await?,
-        make_texture_bmp(&context,"Models/Earth/Textures/Diffuse_2K.bmp").await?,
     ];
 
-    context.clear_color(1.0, 0.0, 1.0, 1.0);
+    let launch_position = first_fix();
+    let earth = Earth::load(&context, launch_position.latitude, launch_position.longitude).await?;
+
+    let shadow_map = ShadowMap::load(&context).await?;
+    let sky = Sky::load(&context).await?;
+    let particles = ParticleSystem::load(&context).await?;
+
+    let document = window().unwrap().document().unwrap();
+    let derived = derived::derive(rocket_data());
+    let units = DisplayUnits::default();
+    let hud = Hud::new(&document)?;
+    let map = MapPanel::new(&document, rocket_data(), launch_position.latitude, launch_position.longitude)?;
+    let altitude = kalman::fuse_altitude(rocket_data());
+    let mapping = StateMapping::default();
+    let timeline = StateTimeline::from_log(rocket_data(), &mapping);
+    let liftoff = flights::liftoff_timestamp(rocket_data(), &timeline);
+    let ghosts = Ghosts::new(&context, &program)?;
+    let charts = make_charts(&document, &derived, &units, &ghosts, liftoff, altitude[0].altitude)?;
+    let launch_site = LaunchSite::new(&context, &program, &LaunchSiteConfig::default(), launch_position, None, altitude[0].altitude as f32)?;
+    let wind = wind::estimate_wind(rocket_data(), &altitude, wind::WIND_BAND_METERS);
+    let wind_arrows = WindArrows::new(&context, &program, &wind, altitude[0].altitude as f32)?;
+    let parachutes = Parachutes::new(&context, &program, rocket_data(), &altitude, &timeline, default_parachutes())?;
+
+    let report = validation::validate(rocket_data());
+    if !report.is_clean() {
+        console::warn_1(&JsValue::from_str(&report.to_string()));
+    }
+
+    context.clear_color(0.0, 0.0, 0.0, 1.0);
 
     unsafe {
-        GLOBAL_DATA = Some(GlobalData{
+        *addr_of_mut!(GLOBAL_DATA) = Some(GlobalData{
             canvas: canvas,
             ctx: context,
             program: program,
             rocket_vao: rocket_vao,
             rocket_tex: rocket_tex,
+            rocket_bounds: rocket_bounds,
+            rocket_parts: rocket_parts,
+            parachutes: parachutes,
+            particles: particles,
+            launch_site: launch_site,
+            wind_arrows: wind_arrows,
+            ghosts: ghosts,
+            shadow_map: shadow_map,
             planet_models: planet_models,
             planet_textures: planet_textures,
-            mvp_location: mvp_uniform_location,
-            tex_location: texture_uniform_location,
-            rotate_location: rotate_uniform_location,
+            earth: earth,
+            sky: sky,
+            charts: charts,
+            hud: hud,
+            map: map,
+            mapping: mapping,
+            timeline: timeline,
+            liftoff: liftoff,
+            derived: derived,
+            altitude: altitude,
+            units: units,
             rocket_vertex_count: rocket_vert_count,
-            opacity_location: opacity_uniform_location,
-            frame_count: 0
+            frame_count: 0,
+            recording: None
 
         });
     }
     Ok(())
 }
 
+// Where the log's GPS first locked, the launch site unless one is configured.
+fn first_fix() -> GeoPosition {
+    rocket_data().iter()
+        .find(|rd| rd.position_lock != 0)
+        .map(|rd| GeoPosition { latitude: rd.latitude, longitude: rd.longitude })
+        .unwrap_or(GeoPosition { latitude: 0.0, longitude: 0.0 })
+}
+
+// Rebuilds the pad, ground station and terrain from a JSON launch site description, see
+// launch_site::LaunchSiteConfig.
+#[wasm_bindgen]
+pub async fn set_launch_site(config: String) -> Result<(), JsValue> {
+    let config = LaunchSiteConfig::parse(&config).map_err(|e| JsValue::from_str(&e))?;
+    let origin = config.site.unwrap_or_else(first_fix);
+    let terrain = load_terrain(&config, &origin).await?;
+
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    gd.launch_site = LaunchSite::new(&gd.ctx, &gd.program, &config, origin, terrain, gd.altitude[0].altitude as f32)?;
+    gd.earth.set_launch_site(origin.latitude, origin.longitude);
+    gd.map.set_pad(rocket_data(), origin.latitude, origin.longitude);
+    Ok(())
+}
+
+// Replaces the default rocket_state code to phase mapping, given as "code=name" pairs like "1=pad, 3=boost".
+#[wasm_bindgen]
+pub fn set_state_mapping(mapping: String) -> Result<(), JsValue> {
+    let mapping = StateMapping::parse(&mapping).map_err(|e| JsValue::from_str(&e))?;
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    gd.timeline = StateTimeline::from_log(rocket_data(), &mapping);
+    gd.parachutes.set_timeline(rocket_data(), &gd.timeline);
+    gd.liftoff = flights::liftoff_timestamp(rocket_data(), &gd.timeline);
+    for flight in gd.ghosts.flights.iter_mut() {
+        flight.set_mapping(&mapping);
+    }
+    gd.mapping = mapping;
+    let document = window().unwrap().document().unwrap();
+    gd.charts = make_charts(&document, &gd.derived, &gd.units, &gd.ghosts, gd.liftoff, gd.altitude[0].altitude)?;
+    Ok(())
+}
+
+// Loads another log to fly alongside the main one, lined up at liftoff, with its curves added to the charts.
+#[wasm_bindgen]
+pub fn add_flight(name: String, csv: String) -> Result<(), JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    let rows = csvreader::get_csv_vec(csv.as_bytes()).map_err(|e| JsValue::from_str(&format!("{}: {}", name, e)))?;
+    let flight = Flight::new(&name, rows, &gd.mapping).map_err(|e| JsValue::from_str(&e))?;
+    gd.ghosts.flights.push(flight);
+    let document = window().unwrap().document().unwrap();
+    gd.charts = make_charts(&document, &gd.derived, &gd.units, &gd.ghosts, gd.liftoff, gd.altitude[0].altitude)?;
+    Ok(())
+}
+
+// Removes every flight added with add_flight.
+#[wasm_bindgen]
+pub fn clear_flights() -> Result<(), JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    gd.ghosts.flights.clear();
+    let document = window().unwrap().document().unwrap();
+    gd.charts = make_charts(&document, &gd.derived, &gd.units, &gd.ghosts, gd.liftoff, gd.altitude[0].altitude)?;
+    Ok(())
+}
+
+// Loads an OpenRocket CSV export to fly next to the log and overlay on the charts, replacing any earlier
+// one. Its liftoff is lined up with the log's, see openrocket::parse_openrocket_csv.
+#[wasm_bindgen]
+pub fn set_simulation(csv: String) -> Result<(), JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    gd.ghosts.simulation = Some(openrocket::parse_openrocket_csv(&csv).map_err(|e| JsValue::from_str(&e))?);
+    let document = window().unwrap().document().unwrap();
+    gd.charts = make_charts(&document, &gd.derived, &gd.units, &gd.ghosts, gd.liftoff, gd.altitude[0].altitude)?;
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn clear_simulation() -> Result<(), JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    gd.ghosts.simulation = None;
+    let document = window().unwrap().document().unwrap();
+    gd.charts = make_charts(&document, &gd.derived, &gd.units, &gd.ghosts, gd.liftoff, gd.altitude[0].altitude)?;
+    Ok(())
+}
+
+// Charts of the main flight with the compared flights and simulation overlaid, all on the main flight's time
+// axis. `pad_altitude` puts the simulated altitude on the barometer datum.
+fn make_charts(document: &Document, derived: &[derived::DerivedSample], units: &DisplayUnits, ghosts: &Ghosts, liftoff: u32, pad_altitude: f64) -> Result<StripCharts, JsValue> {
+    let rows = rocket_data();
+    let mut charts = StripCharts::new(document, rows, derived, units)?;
+    let liftoff_seconds = liftoff.saturating_sub(rows.first().map(|rd| rd.timestamp).unwrap_or(0)) as f32 / 1000.0;
+    for (i, flight) in ghosts.flights.iter().enumerate() {
+        let (r, g, b) = flights::flight_color(i + 1);
+        charts.add_overlay(&flight.rows, &flight.derived, units, flight.time_shift(liftoff_seconds), plotters::style::RGBColor(r, g, b));
+    }
+    if let Some(simulation) = &ghosts.simulation {
+        let (r, g, b) = flights::SIMULATION_COLOR;
+        let time_shift = liftoff_seconds - simulation.liftoff() as f32;
+        charts.add_simulation(simulation, units, time_shift, pad_altitude, plotters::style::RGBColor(r, g, b));
+    }
+    Ok(charts)
+}
+
+// Sets how long the named parachute ("drogue" or "main") takes to inflate after it comes out.
+#[wasm_bindgen]
+pub fn set_parachute_inflation(name: String, seconds: f64) -> Result<(), JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    gd.parachutes.set_inflation_seconds(&name, seconds).map_err(|e| JsValue::from_str(&e))
+}
+
+// Switches the units of the HUD and charts, e.g. "imperial", "metric, ft" or "imperial, m/s, m/s^2".
+#[wasm_bindgen]
+pub fn set_display_units(units: String) -> Result<(), JsValue> {
+    let units = DisplayUnits::parse(&units).map_err(|e| JsValue::from_str(&e))?;
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    let document = window().unwrap().document().unwrap();
+    gd.charts = make_charts(&document, &gd.derived, &units, &gd.ghosts, gd.liftoff, gd.altitude[0].altitude)?;
+    gd.units = units;
+    Ok(())
+}
+
+// Data-quality report for the loaded log as JSON, see validation::ValidationReport.
+#[wasm_bindgen]
+pub fn validate_log() -> String {
+    validation::validate(rocket_data()).to_json()
+}
+
+// Wind estimated from the drift under parachute as CSV, one altitude band per line, see wind::wind_profile_csv.
+#[wasm_bindgen]
+pub fn wind_profile() -> Result<String, JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    Ok(wind::wind_profile_csv(&wind::estimate_wind(rocket_data(), &gd.altitude, wind::WIND_BAND_METERS)))
+}
+
+// Refetches and recompiles every shader from the shaders/ directory. A program that fails to compile keeps
+// running its previous version and its log is reported through the returned error.
+#[wasm_bindgen]
+pub async fn reload_shaders() -> Result<(), JsValue> {
+    let files = [&ROCKET_SHADER, &SHADOW_SHADER, &SKY_SHADER, &EARTH_SHADER, &PARTICLE_SHADER];
+    let mut sources = Vec::new();
+    for f in files.iter() {
+        sources.push(load_shader_sources(f).await?);
+    }
+
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    let ctx = gd.ctx.clone();
+    let programs = vec![&mut gd.program, &mut gd.shadow_map.program, &mut gd.sky.program, &mut gd.earth.program, &mut gd.particles.program];
+
+    let mut errors = Vec::new();
+    for ((program, source), f) in programs.into_iter().zip(sources.iter()).zip(files.iter()) {
+        match compile_shader_sources(&ctx, source) {
+            Ok(new_program) => {
+                let old = std::mem::replace(program, new_program);
+                ctx.delete_program(Some(&old.program));
+            }
+            Err(log) => {
+                let message = format!("{} / {}: {}", f.vert, f.frag, log);
+                console::error_1(&JsValue::from_str(&message));
+                errors.push(message);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(JsValue::from_str(&errors.join("\n")))
+    }
+}
+
 #[wasm_bindgen]
 pub fn run_frame() {
-    let mut gd = unsafe { GLOBAL_DATA.take().unwrap() };
-    let cwidth = gd.canvas.width() as f32;
-    let cheight = gd.canvas.height() as f32;
+    let gd = global_data().unwrap();
+    let (width, height) = (gd.canvas.width() as i32, gd.canvas.height() as i32);
+    let elapsed = gd.frame_count as f64 / FRAMES_PER_SECOND;
+    let rocket_data_row_index = render_scene(gd, elapsed, None, width, height);
+
+    if let Err(e) = gd.hud.draw(rocket_data(), &gd.derived, &gd.altitude, &gd.timeline, &gd.units, rocket_data_row_index) {
+        console::error_1(&e);
+    }
+    if let Err(e) = gd.map.draw(rocket_data(), &gd.altitude, &gd.units, rocket_data_row_index, gd.altitude[0].altitude) {
+        console::error_1(&e);
+    }
+    if gd.frame_count.is_multiple_of(CHART_FRAME_INTERVAL) {
+        if let Err(e) = gd.charts.draw(rocket_data_row_index) {
+            console::error_1(&e);
+        }
+    }
+
+    gd.frame_count += 1;
+}
+
+// Starts rendering the replay from the beginning into a `width` x `height` target at a fixed
+// `frames_per_second`, one record_frame call per frame. The on-screen canvas is left alone meanwhile.
+#[wasm_bindgen]
+pub fn start_recording(width: u32, height: u32, frames_per_second: f64) -> Result<(), JsValue> {
+    if !frames_per_second.is_finite() || frames_per_second <= 0.0 {
+        return Err(JsValue::from_str("frames per second must be positive"));
+    }
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    let target = OffscreenTarget::new(&gd.ctx, width, height)?;
+    if let Some(old) = gd.recording.replace(Recording { target, frames_per_second, frame: 0 }) {
+        old.target.delete(&gd.ctx);
+    }
+    // the live view has been running the plume on its own clock, start it over with the recording
+    gd.particles.reset();
+    Ok(())
+}
+
+// Renders the next frame of the recording and returns it as RGBA bytes, top row first, or nothing once the
+// log has played out. Only the 3d scene is recorded, not the HUD, map and charts.
+#[wasm_bindgen]
+pub fn record_frame() -> Result<Option<Vec<u8>>, JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    let recording = gd.recording.as_mut().ok_or_else(|| JsValue::from_str("not recording"))?;
+    let elapsed = recording.frame as f64 / recording.frames_per_second;
+    if row_index(elapsed) >= rocket_data().len() {
+        return Ok(None);
+    }
+    recording.frame += 1;
+    let (framebuffer, width, height) = (recording.target.framebuffer.clone(), recording.target.width, recording.target.height);
+
+    render_scene(gd, elapsed, Some(&framebuffer), width, height);
+    gd.recording.as_ref().unwrap().target.read_pixels(&gd.ctx).map(Some)
+}
+
+#[wasm_bindgen]
+pub fn stop_recording() -> Result<(), JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    if let Some(recording) = gd.recording.take() {
+        recording.target.delete(&gd.ctx);
+    }
+    Ok(())
+}
+
+// Renders the frame last shown on screen again at `width` x `height`, e.g. 3840x2160 for a poster, and
+// returns it as PNG file bytes. Like recordings it holds the 3d scene only.
+#[wasm_bindgen]
+pub fn capture_screenshot(width: u32, height: u32) -> Result<Vec<u8>, JsValue> {
+    let gd = global_data().ok_or_else(|| JsValue::from_str("not started"))?;
+    let target = OffscreenTarget::new(&gd.ctx, width, height)?;
+    let elapsed = gd.frame_count.saturating_sub(1) as f64 / FRAMES_PER_SECOND;
+    render_scene(gd, elapsed, Some(&target.framebuffer), target.width, target.height);
+    let pixels = target.read_pixels(&gd.ctx);
+    target.delete(&gd.ctx);
+    capture::encode_png(&pixels?, width, height).map_err(|e| JsValue::from_str(&e))
+}
+
+// PNG file bytes of `rgba` pixels as returned by record_frame.
+#[wasm_bindgen]
+pub fn encode_png(width: u32, height: u32, rgba: Vec<u8>) -> Result<Vec<u8>, JsValue> {
+    capture::encode_png(&rgba, width, height).map_err(|e| JsValue::from_str(&e))
+}
+
+// Playback row `elapsed_seconds` of display time after the replay started.
+fn row_index(elapsed_seconds: f64) -> usize {
+    let time_seconds = START_TIME_SECONDS + elapsed_seconds * TIME_SCALE;
+    (time_seconds / ROCKET_DATA_TIMESTEP_SECONDS).round() as usize
+}
+
+// Draws the 3d scene `elapsed_seconds` into the replay into `target`, the canvas when None, and returns the
+// row shown. Everything but the exhaust plume is a function of the elapsed time; the plume is simulated
+// from frame to frame, so it matches between runs only when stepped through the same times from a reset.
+fn render_scene(gd: &mut GlobalData, elapsed_seconds: f64, target: Option<&WebGlFramebuffer>, width: i32, height: i32) -> usize {
+    let cwidth = width as f32;
+    let cheight = height as f32;
 
     // let rot = glm::rotate(&glm::identity(), gd.frame_count as f32 / 100.0, &glm::vec3(0.0, 0.0, 1.0));
     // let perspective = glm::perspective(cheight/cwidth, 90.0, 0.1, 100.0);
 
-    let rocket_data_row_index = get_rocket_data_row_index(gd.frame_count);
+    let rocket_data_row_index = row_index(elapsed_seconds);
     // console::log_1(&JsValue::from_f64(rocket_data_row_index as f64));
-    let rd;
-    unsafe {
-        if rocket_data_row_index < ROCKET_DATA_VEC.len() {
-            rd = &ROCKET_DATA_VEC[rocket_data_row_index];
-        } else {
-            rd = &ROCKET_DATA_VEC[ROCKET_DATA_VEC.len() - 1];
-        }
-    }
+    let rows = rocket_data();
+    let rd = &rows[rocket_data_row_index.min(rows.len() - 1)];
 
     let roll = rd.mx.atan2(rd.mz) as f32;
 
-    let z = (rd.barometer_altitude * 1.0) as f32;
+    let estimate = &gd.altitude[rocket_data_row_index.min(gd.altitude.len() - 1)];
+    let z = estimate.altitude as f32;
 
     let zoom_level =
         if z < 800.0{
@@ -218,53 +551,92 @@ pub fn run_frame() {
             * glm::scale(&glm::identity(), &glm::vec3(plane_scale1, plane_scale1, plane_scale1))
         ;
 
-    let camera_rot = gd.frame_count as f32 / 1000.0;
+    let camera_rot = (elapsed_seconds * CAMERA_RADIANS_PER_SECOND) as f32;
+    let camera_position = glm::vec3(camera_rot.sin() * 7.0,camera_rot.cos() * 7.0,20.0 + z);
     let view: glm::Mat4 = glm::look_at(
-        &glm::vec3(camera_rot.sin() * 7.0,camera_rot.cos() * 7.0,20.0 + z),
+        &camera_position,
         &glm::vec3(0.0, 0.0, z),
         &glm::vec3(0.0,0.0,1.0)
     );
 
     let proj: glm::Mat4 = glm::perspective(cwidth/cheight, 45.0, 0.1, 100000.0);
+    // the globe gets its own depth range, the near plane only has to stay in front of the ground below the camera
+    let globe_proj: glm::Mat4 = glm::perspective(cwidth/cheight, 45.0, (camera_position.z * 0.5).max(1.0), 2.0 * EARTH_RADIUS_METERS as f32);
 
     let mvp_rocket = proj * view * rocket_model;
     let mvp_planet0 = proj * view * planet_model0;
     let mvp_planet1 = proj * view * planet_model1;
 
+    let sun_direction = glm::normalize(&glm::make_vec3(&SUN_DIRECTION));
+    let light_vp = fit_light_view_projection(&sun_direction, &gd.rocket_bounds, &rocket_model);
+
+    let mut shadow_casters = vec![(&gd.rocket_vao, gd.rocket_vertex_count, light_vp * rocket_model)];
+    for part in &gd.rocket_parts.parts {
+        shadow_casters.push((&part.vao, part.vertex_count, light_vp * rocket_model * part.transform(rd)));
+    }
+    gd.shadow_map.render(&gd.ctx, &shadow_casters);
+
+    gd.ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, target);
+    gd.ctx.viewport(0, 0, width, height);
     gd.ctx.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
 
+    gd.sky.render(&gd.ctx, &proj, &view, &sun_direction, camera_position.z);
+    gd.earth.render(&gd.ctx, &(globe_proj * view), &camera_position, &sun_direction);
+    gd.ctx.clear(WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+
+    gd.program.use_program(&gd.ctx);
+
+    gd.program.set_vec3(&gd.ctx, "light", &sun_direction);
+    gd.program.set_texture(&gd.ctx, "shadow_map", gd.shadow_map.depth_texture.as_ref(), 1);
 
 
     gd.ctx.bind_vertex_array(Some(&gd.rocket_vao));
-    bind_shader_texture(&gd.ctx, gd.rocket_tex.as_ref(), gd.tex_location.as_ref(), 0);
-    gd.ctx.uniform_matrix4fv_with_f32_array(gd.mvp_location.as_ref(), false, mvp_rocket.data.as_slice());
-    gd.ctx.uniform_matrix4fv_with_f32_array(gd.rotate_location.as_ref(), false, rocket_rotate.data.as_slice());
-    gd.ctx.uniform1f(gd.opacity_location.as_ref(), 1.0);
+    gd.program.set_texture(&gd.ctx, "tex", gd.rocket_tex.as_ref(), 0);
+    gd.program.set_mat4(&gd.ctx, "mvp", &mvp_rocket);
+    gd.program.set_mat4(&gd.ctx, "rotate", &rocket_rotate);
+    gd.program.set_mat4(&gd.ctx, "light_mvp", &(light_vp * rocket_model));
+    gd.program.set_f32(&gd.ctx, "receive_shadow", 0.0);
+    gd.program.set_f32(&gd.ctx, "opacity", 1.0);
     gd.ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, gd.rocket_vertex_count);
+    gd.rocket_parts.render(&gd.ctx, &gd.program, rd, &mvp_rocket, &(light_vp * rocket_model), &rocket_rotate);
+    gd.launch_site.render(&gd.ctx, &gd.program, &(proj * view), &light_vp);
+    gd.wind_arrows.render(&gd.ctx, &gd.program, &(proj * view));
+    // shock cords are tied to the nose
+    let attach = (rocket_model * glm::vec4(0.0, 0.0, gd.rocket_bounds.1.z, 1.0)).xyz();
+    gd.parachutes.render(&gd.ctx, &gd.program, rocket_data(), rocket_data_row_index, &(proj * view), &attach);
+    let nozzle = (rocket_model * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz();
+    gd.particles.update(rocket_data(), rocket_data_row_index.min(gd.altitude.len() - 1), &gd.timeline, estimate, &nozzle);
 
     gd.ctx.bind_vertex_array(Some(&gd.planet_models[zoom_level.0].0));
-    bind_shader_texture(&gd.ctx, gd.planet_textures[zoom_level.0].as_ref(), gd.tex_location.as_ref(), 0);
-    gd.ctx.uniform_matrix4fv_with_f32_array(gd.mvp_location.as_ref(), false, mvp_planet0.data.as_slice());
-    gd.ctx.uniform_matrix4fv_with_f32_array(gd.rotate_location.as_ref(), false, planet_rotate.data.as_slice());
-    gd.ctx.uniform1f(gd.opacity_location.as_ref(), 1.0);
+    gd.program.set_texture(&gd.ctx, "tex", gd.planet_textures[zoom_level.0].as_ref(), 0);
+    gd.program.set_mat4(&gd.ctx, "mvp", &mvp_planet0);
+    gd.program.set_mat4(&gd.ctx, "rotate", &planet_rotate);
+    gd.program.set_mat4(&gd.ctx, "light_mvp", &(light_vp * planet_model0));
+    gd.program.set_f32(&gd.ctx, "receive_shadow", 1.0);
+    // past the coarsest tile the ground fades out and leaves the globe
+    let top_layer = zoom_level.0 + 1 == gd.planet_models.len();
+    gd.program.set_f32(&gd.ctx, "opacity", if top_layer { (1.0 - zoom_level.1).max(0.0) } else { 1.0 });
     gd.ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, gd.planet_models[zoom_level.0].1);
 
-    gd.ctx.bind_vertex_array(Some(&gd.planet_models[zoom_level.0 + 1].0));
-    bind_shader_texture(&gd.ctx, gd.planet_textures[zoom_level.0 + 1].as_ref(), gd.tex_location.as_ref(), 0);
-    gd.ctx.uniform_matrix4fv_with_f32_array(gd.mvp_location.as_ref(), false, mvp_planet1.data.as_slice());
-    gd.ctx.uniform_matrix4fv_with_f32_array(gd.rotate_location.as_ref(), false, planet_rotate.data.as_slice());
-    gd.ctx.uniform1f(gd.opacity_location.as_ref(), zoom_level.1);
-    gd.ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, gd.planet_models[zoom_level.0+1].1);
+    if !top_layer {
+        gd.ctx.bind_vertex_array(Some(&gd.planet_models[zoom_level.0 + 1].0));
+        gd.program.set_texture(&gd.ctx, "tex", gd.planet_textures[zoom_level.0 + 1].as_ref(), 0);
+        gd.program.set_mat4(&gd.ctx, "mvp", &mvp_planet1);
+        gd.program.set_mat4(&gd.ctx, "rotate", &planet_rotate);
+        gd.program.set_mat4(&gd.ctx, "light_mvp", &(light_vp * planet_model1));
+        gd.program.set_f32(&gd.ctx, "receive_shadow", 1.0);
+        gd.program.set_f32(&gd.ctx, "opacity", zoom_level.1);
+        gd.ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, gd.planet_models[zoom_level.0+1].1);
+    }
 
+    // compared flights are see-through, so they go after the ground
+    let trail_start = &gd.altitude[ghosts::trail_start_row(rocket_data(), rocket_data_row_index.min(gd.altitude.len() - 1))];
+    let rocket_mesh = RocketMesh { vao: &gd.rocket_vao, vertex_count: gd.rocket_vertex_count, texture: gd.rocket_tex.as_ref() };
+    let mission_ms = rd.timestamp as i64 - gd.liftoff as i64;
+    gd.ghosts.render(&gd.ctx, &gd.program, &rocket_mesh, &(proj * view), mission_ms, (trail_start.altitude as f32, z), gd.altitude[0].altitude as f32);
 
-    gd.frame_count += 1;
+    gd.particles.render(&gd.ctx, &(proj * view), &view, &camera_position);
 
-    unsafe {
-        GLOBAL_DATA = Some(gd);
-    }
-
-    pub fn get_rocket_data_row_index(frame_count: u64) -> usize {
-        let time_seconds = START_TIME_SECONDS + ((frame_count as f64) / FRAMES_PER_SECOND) * TIME_SCALE;
-        return (time_seconds / ROCKET_DATA_TIMESTEP_SECONDS).round() as usize;
-    }
+    gd.ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+    rocket_data_row_index
 }
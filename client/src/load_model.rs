@@ -7,11 +7,17 @@ use obj::*;
 use std::io::BufReader;
 use tinybmp::{Bmp, DynamicBmp};
 use embedded_graphics::pixelcolor::Rgb888;
+use nalgebra_glm as glm;
+
+pub type Mesh = (Vec<f32>, Vec<f32>, Vec<f32>);
 
 pub async fn load_file(path: &str) -> Result<Vec<u8>, JsValue> {
     let response = JsFuture::from(window().unwrap().fetch_with_str(path)).await?;
 
     let response = response.dyn_into::<Response>()?;
+    if !response.ok() {
+        return Err(JsValue::from_str(&format!("{}: {} {}", path, response.status(), response.status_text())));
+    }
 
     let buff = JsFuture::from(response.array_buffer()?).await?;
 
@@ -68,7 +74,91 @@ pub fn make_plane() -> (Vec<f32>, Vec<f32>, Vec<f32>){
     return (verts, norms, uvs);
 }
 
+// Flat-shaded box between `min` and `max`, every vertex using `uv`.
+pub fn make_box(min: &glm::Vec3, max: &glm::Vec3, uv: [f32; 2]) -> Mesh {
+    let corner = |i: usize| [
+        if i & 1 == 0 { min.x } else { max.x },
+        if i & 2 == 0 { min.y } else { max.y },
+        if i & 4 == 0 { min.z } else { max.z },
+    ];
+    // corner indices of each face, counter clockwise seen from outside, with the face normal
+    let faces: [([usize; 4], [f32; 3]); 6] = [
+        ([0, 4, 6, 2], [-1.0, 0.0, 0.0]),
+        ([1, 3, 7, 5], [1.0, 0.0, 0.0]),
+        ([0, 1, 5, 4], [0.0, -1.0, 0.0]),
+        ([2, 6, 7, 3], [0.0, 1.0, 0.0]),
+        ([0, 2, 3, 1], [0.0, 0.0, -1.0]),
+        ([4, 5, 7, 6], [0.0, 0.0, 1.0]),
+    ];
+
+    let (mut verts, mut norms, mut uvs) = (Vec::new(), Vec::new(), Vec::new());
+    for (quad, normal) in faces.iter() {
+        for i in [0, 1, 2, 0, 2, 3].iter() {
+            verts.extend_from_slice(&corner(quad[*i]));
+            norms.extend_from_slice(normal);
+            uvs.extend_from_slice(&uv);
+        }
+    }
+    (verts, norms, uvs)
+}
+
+pub fn transform_mesh((verts, norms, uvs): Mesh, transform: &glm::Mat4) -> Mesh {
+    let verts = verts.chunks(3).flat_map(|v| {
+        let p = transform * glm::vec4(v[0], v[1], v[2], 1.0);
+        vec![p.x, p.y, p.z]
+    }).collect();
+    let norms = norms.chunks(3).flat_map(|n| {
+        let n = transform * glm::vec4(n[0], n[1], n[2], 0.0);
+        vec![n.x, n.y, n.z]
+    }).collect();
+    (verts, norms, uvs)
+}
+
+// Rotation taking +z onto `direction`.
+pub fn align_z(direction: &glm::Vec3) -> glm::Mat4 {
+    let z = glm::vec3(0.0, 0.0, 1.0);
+    if glm::dot(direction, &z) < -0.9999 {
+        // rotation_between has no unique answer for opposite vectors
+        return glm::rotate(&glm::identity(), std::f32::consts::PI, &glm::vec3(1.0, 0.0, 0.0));
+    }
+    glm::quat_to_mat4(&glm::quat_rotation(&z, direction))
+}
+
+// Model matrix stretching a mesh spanning z 0..1 from `a` to `b`.
+pub fn segment(a: &glm::Vec3, b: &glm::Vec3) -> glm::Mat4 {
+    let length = glm::distance(a, b).max(1e-4);
+    glm::translate(&glm::identity(), a)
+        * align_z(&((b - a) / length))
+        * glm::scale(&glm::identity(), &glm::vec3(1.0, 1.0, length))
+}
+
+pub async fn load_png(path: &str) -> Result<(Vec<u8>, i32, i32), JsValue> {
+    let file = load_file(path).await?;
+    let decoder = png::Decoder::new(file.as_slice());
+    let (info, mut reader) = decoder.read_info().ok().ok_or(JsValue::from_str("bad png format"))?;
+    if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+        return Err(JsValue::from_str("png must be 8 bit RGBA"));
+    }
+
+    let mut data = vec![0u8; info.buffer_size()];
+    reader.next_frame(&mut data).ok().ok_or(JsValue::from_str("corrupt png"))?;
+
+    // flip to the bottom-up row order the bmp loader produces, so uvs mean the same thing for both
+    let flipped = data.chunks(info.line_size).rev().flatten().copied().collect();
+
+    Ok((flipped, info.width as i32, info.height as i32))
+}
+
 pub async fn load_mesh(path: &str) -> Result<(Vec<f32>, Vec<f32>, Vec<f32>), JsValue> {
+    load_mesh_filtered(path, None).await
+}
+
+// Loads only the obj objects whose name starts with `object_name`, e.g. "Earth" out of Earth_2K.obj.
+pub async fn load_mesh_object(path: &str, object_name: &str) -> Result<(Vec<f32>, Vec<f32>, Vec<f32>), JsValue> {
+    load_mesh_filtered(path, Some(object_name)).await
+}
+
+async fn load_mesh_filtered(path: &str, object_name: Option<&str>) -> Result<(Vec<f32>, Vec<f32>, Vec<f32>), JsValue> {
     let obj = load_obj(path).await?;
 
     let mut verts = Vec::new();
@@ -76,6 +166,11 @@ pub async fn load_mesh(path: &str) -> Result<(Vec<f32>, Vec<f32>, Vec<f32>), JsV
     let mut uvs = Vec::new();
 
     for object in obj.objects {
+        if let Some(name) = object_name {
+            if !object.name.starts_with(name) {
+                continue;
+            }
+        }
         for group in object.groups {
             for poly in group.polys {
                 let idxs = poly.0;
@@ -130,4 +225,17 @@ pub async fn load_mesh(path: &str) -> Result<(Vec<f32>, Vec<f32>, Vec<f32>), JsV
     }
 
     return Ok((verts, norms, uvs));
-}
\ No newline at end of file
+}
+
+pub fn mesh_bounds(verts: &[f32]) -> (glm::Vec3, glm::Vec3) {
+    let mut min = glm::vec3(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = glm::vec3(f32::MIN, f32::MIN, f32::MIN);
+
+    for vert in verts.chunks(3) {
+        let v = glm::vec3(vert[0], vert[1], vert[2]);
+        min = glm::min2(&min, &v);
+        max = glm::max2(&max, &v);
+    }
+
+    (min, max)
+}
@@ -0,0 +1,327 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::csvreader::{get_csv_vec, write_csv_vec};
+use crate::flight_state::{FlightState, StateMapping, StateTimeline};
+use crate::rocket_data::{RocketData, COLUMNS};
+
+// Binary log records are the RocketData fields in declaration order, little-endian, with the u32 columns
+// as 4 bytes and the f64 columns as 8. There is no header or framing, a file is a plain array of records.
+pub const RECORD_BYTES: usize = 4 * 4 + 17 * 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogFormat {
+    Csv,
+    Binary,
+    JsonLines,
+}
+
+impl LogFormat {
+    // Picks the format from the file extension: .csv, .bin, or .jsonl / .ndjson.
+    pub fn from_path(path: &Path) -> Option<LogFormat> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(LogFormat::Csv),
+            "bin" => Some(LogFormat::Binary),
+            "jsonl" | "ndjson" => Some(LogFormat::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+pub fn encode_record(rd: &RocketData, out: &mut Vec<u8>) {
+    out.extend_from_slice(&rd.timestamp.to_le_bytes());
+    for value in [rd.ax, rd.ay, rd.az, rd.gx, rd.gy, rd.gz, rd.mx, rd.my, rd.mz, rd.latitude, rd.longitude, rd.altitude].iter() {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&rd.satellite_count.to_le_bytes());
+    out.extend_from_slice(&rd.position_lock.to_le_bytes());
+    for value in [rd.temperature, rd.pressure, rd.barometer_altitude].iter() {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&rd.rocket_state.to_le_bytes());
+    for value in [rd.l1_extension, rd.l2_extension].iter() {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+struct RecordCursor<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> RecordCursor<'a> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut field = [0; N];
+        field.copy_from_slice(&self.bytes[self.offset..self.offset + N]);
+        self.offset += N;
+        field
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    fn f64(&mut self) -> f64 {
+        f64::from_le_bytes(self.take())
+    }
+}
+
+// `record` must be RECORD_BYTES long.
+pub fn decode_record(record: &[u8]) -> RocketData {
+    let mut c = RecordCursor { bytes: record, offset: 0 };
+    // struct fields are evaluated in the order written, which is the record order
+    RocketData {
+        timestamp: c.u32(),
+        ax: c.f64(),
+        ay: c.f64(),
+        az: c.f64(),
+        gx: c.f64(),
+        gy: c.f64(),
+        gz: c.f64(),
+        mx: c.f64(),
+        my: c.f64(),
+        mz: c.f64(),
+        latitude: c.f64(),
+        longitude: c.f64(),
+        altitude: c.f64(),
+        satellite_count: c.u32(),
+        position_lock: c.u32(),
+        temperature: c.f64(),
+        pressure: c.f64(),
+        barometer_altitude: c.f64(),
+        rocket_state: c.u32(),
+        l1_extension: c.f64(),
+        l2_extension: c.f64(),
+    }
+}
+
+pub fn read_log<R: Read>(format: LogFormat, mut reader: R) -> Result<Vec<RocketData>, String> {
+    match format {
+        LogFormat::Csv => get_csv_vec(reader).map_err(|e| e.to_string()),
+        LogFormat::Binary => {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+            if bytes.len() % RECORD_BYTES != 0 {
+                return Err(format!("{} bytes is not a whole number of {} byte records", bytes.len(), RECORD_BYTES));
+            }
+            Ok(bytes.chunks(RECORD_BYTES).map(decode_record).collect())
+        }
+        LogFormat::JsonLines => {
+            let mut rows = Vec::new();
+            for (i, line) in BufReader::new(reader).lines().enumerate() {
+                let line = line.map_err(|e| e.to_string())?;
+                if !line.trim().is_empty() {
+                    rows.push(serde_json::from_str(&line).map_err(|e| format!("line {}: {}", i + 1, e))?);
+                }
+            }
+            Ok(rows)
+        }
+    }
+}
+
+pub fn write_log<W: Write>(format: LogFormat, rows: &[RocketData], mut writer: W) -> Result<(), String> {
+    match format {
+        LogFormat::Csv => write_csv_vec(rows, writer).map_err(|e| e.to_string()),
+        LogFormat::Binary => {
+            let mut bytes = Vec::with_capacity(rows.len() * RECORD_BYTES);
+            for rd in rows {
+                encode_record(rd, &mut bytes);
+            }
+            writer.write_all(&bytes).map_err(|e| e.to_string())
+        }
+        LogFormat::JsonLines => {
+            for rd in rows {
+                serde_json::to_writer(&mut writer, rd).map_err(|e| e.to_string())?;
+                writer.write_all(b"\n").map_err(|e| e.to_string())?;
+            }
+            writer.flush().map_err(|e| e.to_string())
+        }
+    }
+}
+
+// Keeps the rows between `from` and `to` seconds after the first row, both inclusive, the same time axis
+// as the charts.
+pub fn trim_time(rows: &[RocketData], from: Option<f64>, to: Option<f64>) -> Vec<RocketData> {
+    let start = rows.first().map(|rd| rd.timestamp).unwrap_or(0);
+    rows.iter()
+        .filter(|rd| {
+            let t = rd.timestamp.saturating_sub(start) as f64 / 1000.0;
+            from.is_none_or(|from| t >= from) && to.is_none_or(|to| t <= to)
+        })
+        .cloned()
+        .collect()
+}
+
+// Keeps the rows from the first entry into `from` up to the last row of the last `to` phase.
+pub fn trim_states(rows: &[RocketData], mapping: &StateMapping, from: FlightState, to: FlightState) -> Result<Vec<RocketData>, String> {
+    let timeline = StateTimeline::from_log(rows, mapping);
+    let start = timeline.first(from).ok_or_else(|| format!("log never enters {}", from))?.start_row;
+    let end = timeline.phases.iter().rev().find(|phase| phase.state == to).ok_or_else(|| format!("log never enters {}", to))?.end_row;
+    if end <= start {
+        return Err(format!("{} ends before {} starts", to, from));
+    }
+    Ok(rows[start..end].to_vec())
+}
+
+fn interpolate(a: &RocketData, b: &RocketData, timestamp: u32) -> RocketData {
+    // rows stamped out of order or at the same time hold the earlier row
+    let span = b.timestamp.saturating_sub(a.timestamp);
+    let t = if span > 0 {
+        (timestamp.saturating_sub(a.timestamp) as f64 / span as f64).min(1.0)
+    } else {
+        0.0
+    };
+    let mix = |x: f64, y: f64| x + (y - x) * t;
+
+    // counts, flags and states are held from the earlier row rather than blended
+    RocketData {
+        timestamp,
+        ax: mix(a.ax, b.ax),
+        ay: mix(a.ay, b.ay),
+        az: mix(a.az, b.az),
+        gx: mix(a.gx, b.gx),
+        gy: mix(a.gy, b.gy),
+        gz: mix(a.gz, b.gz),
+        mx: mix(a.mx, b.mx),
+        my: mix(a.my, b.my),
+        mz: mix(a.mz, b.mz),
+        latitude: mix(a.latitude, b.latitude),
+        longitude: mix(a.longitude, b.longitude),
+        altitude: mix(a.altitude, b.altitude),
+        satellite_count: a.satellite_count,
+        position_lock: a.position_lock,
+        temperature: mix(a.temperature, b.temperature),
+        pressure: mix(a.pressure, b.pressure),
+        barometer_altitude: mix(a.barometer_altitude, b.barometer_altitude),
+        rocket_state: a.rocket_state,
+        l1_extension: mix(a.l1_extension, b.l1_extension),
+        l2_extension: mix(a.l2_extension, b.l2_extension),
+    }
+}
+
+// Linear interpolation onto a fixed `rate` in Hz from the first timestamp. Assumes increasing timestamps,
+// see validation::validate.
+pub fn resample(rows: &[RocketData], rate: f64) -> Vec<RocketData> {
+    let (first, last) = match (rows.first(), rows.last()) {
+        (Some(first), Some(last)) if rate > 0.0 => (first.timestamp, last.timestamp),
+        _ => return Vec::new(),
+    };
+    let period = 1000.0 / rate;
+
+    let mut i = 0;
+    (0..).map(|k| first + (k as f64 * period).round() as u32)
+        .take_while(|timestamp| *timestamp <= last)
+        .map(|timestamp| {
+            while i + 1 < rows.len() && rows[i + 1].timestamp <= timestamp {
+                i += 1;
+            }
+            interpolate(&rows[i], &rows[(i + 1).min(rows.len() - 1)], timestamp)
+        })
+        .collect()
+}
+
+pub fn log_statistics(rows: &[RocketData], mapping: &StateMapping) -> String {
+    let mut text = String::new();
+    let (first, last) = match (rows.first(), rows.last()) {
+        (Some(first), Some(last)) => (first.timestamp, last.timestamp),
+        _ => return "empty log\n".to_string(),
+    };
+    let duration = last.saturating_sub(first) as f64 / 1000.0;
+    text += &format!("rows: {}\n", rows.len());
+    text += &format!("timestamps: {} - {} ms ({:.2} s)\n", first, last, duration);
+    if duration > 0.0 {
+        text += &format!("mean rate: {:.1} Hz\n", (rows.len() - 1) as f64 / duration);
+    }
+
+    text += &format!("\n{:<20} {:>14} {:>14} {:>14}  unit\n", "column", "min", "mean", "max");
+    for (name, value, unit) in COLUMNS.iter().skip(1) {
+        let min = rows.iter().map(value).fold(f64::INFINITY, f64::min);
+        let max = rows.iter().map(value).fold(f64::NEG_INFINITY, f64::max);
+        let mean = rows.iter().map(value).sum::<f64>() / rows.len() as f64;
+        text += &format!("{:<20} {:>14.4} {:>14.4} {:>14.4}  {}\n", name, min, mean, max, unit.symbol());
+    }
+
+    text += "\nstates:\n";
+    for phase in StateTimeline::from_log(rows, mapping).phases {
+        let seconds = phase.end_timestamp.saturating_sub(phase.start_timestamp) as f64 / 1000.0;
+        text += &format!("  {:<16} rows {}..{}, {:.2} s\n", phase.state.to_string(), phase.start_row, phase.end_row, seconds);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csvreader::get_rocket_data;
+
+    fn round_trip(format: LogFormat, rows: &[RocketData]) -> Vec<RocketData> {
+        let mut bytes = Vec::new();
+        write_log(format, rows, &mut bytes).unwrap();
+        read_log(format, bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let rows = get_rocket_data().unwrap()[..500].to_vec();
+        assert_eq!(round_trip(LogFormat::Binary, &rows), rows);
+        assert_eq!(round_trip(LogFormat::JsonLines, &rows), rows);
+        let csv = round_trip(LogFormat::Csv, &rows);
+        assert_eq!(csv.len(), rows.len());
+        assert!(csv.iter().zip(rows.iter()).all(|(a, b)| a.timestamp == b.timestamp && (a.barometer_altitude - b.barometer_altitude).abs() < 1e-6));
+    }
+
+    #[test]
+    fn binary_rejects_partial_records() {
+        let mut bytes = Vec::new();
+        encode_record(&get_rocket_data().unwrap()[0], &mut bytes);
+        assert_eq!(bytes.len(), RECORD_BYTES);
+        bytes.pop();
+        assert!(read_log(LogFormat::Binary, bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn trims_by_time_and_state() {
+        let rows = get_rocket_data().unwrap();
+        let start = rows[0].timestamp;
+        let trimmed = trim_time(&rows, Some(10.0), Some(20.0));
+        assert_eq!(trimmed.first().unwrap().timestamp - start, 10_000);
+        assert_eq!(trimmed.last().unwrap().timestamp - start, 20_000);
+
+        let mapping = StateMapping::default();
+        let boost = trim_states(&rows, &mapping, FlightState::Boost, FlightState::Coast).unwrap();
+        assert_eq!(mapping.state(boost[0].rocket_state), FlightState::Boost);
+        assert_eq!(mapping.state(boost.last().unwrap().rocket_state), FlightState::Coast);
+        assert!(trim_states(&rows, &mapping, FlightState::Drogue, FlightState::Boost).is_err());
+    }
+
+    #[test]
+    fn resamples_onto_a_fixed_rate() {
+        let rows = get_rocket_data().unwrap()[..1001].to_vec();
+        let resampled = resample(&rows, 40.0);
+        assert_eq!(resampled.len(), 401);
+        assert!(resampled.windows(2).all(|w| w[1].timestamp - w[0].timestamp == 25));
+        // halfway between two 10 ms rows
+        let (a, b) = (&rows[2], &rows[3]);
+        let mid = &resampled[1];
+        assert_eq!(mid.timestamp, a.timestamp + 5);
+        assert!((mid.barometer_altitude - (a.barometer_altitude + b.barometer_altitude) / 2.0).abs() < 1e-9);
+        assert!(resample(&rows, 0.0).is_empty());
+    }
+
+    #[test]
+    fn interpolation_holds_rows_out_of_order() {
+        let rows = get_rocket_data().unwrap();
+        let (a, b) = (&rows[10], &rows[9]);
+        // b is stamped before a, and the timestamp falls before both
+        let held = interpolate(a, b, b.timestamp - 5);
+        assert_eq!(held.barometer_altitude, a.barometer_altitude);
+        let same = interpolate(a, a, a.timestamp + 5);
+        assert_eq!(same.barometer_altitude, a.barometer_altitude);
+
+        let mut backstep = rows[..100].to_vec();
+        backstep[50].timestamp = backstep[49].timestamp - 30;
+        let resampled = resample(&backstep, 100.0);
+        assert!(resampled.iter().all(|rd| rd.barometer_altitude.is_finite()));
+        assert!(log_statistics(&backstep, &StateMapping::default()).contains("rows: 100"));
+    }
+}
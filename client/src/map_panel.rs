@@ -0,0 +1,153 @@
+use std::f64::consts::TAU;
+
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, Document, HtmlCanvasElement};
+
+use crate::kalman::AltitudeEstimate;
+use crate::recovery::{compass_point, distance_bearing, fixes_by, ground_track, predict_landing, TrackPoint};
+use crate::rocket_data::RocketData;
+use crate::units::{DisplayUnits, Unit};
+
+// Map radius choices in metres, the smallest that holds the track and prediction is used.
+const RANGES: [f64; 10] = [100.0, 200.0, 500.0, 1_000.0, 2_000.0, 5_000.0, 10_000.0, 20_000.0, 50_000.0, 100_000.0];
+const PADDING: f64 = 10.0;
+const LINE_HEIGHT: f64 = 18.0;
+const TEXT_LINES: usize = 3;
+const FONT: &str = "12px monospace";
+
+// Top-down recovery map on the "map" overlay canvas, centred on the pad with north up.
+pub struct MapPanel {
+    canvas: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+    track: Vec<TrackPoint>,
+}
+
+impl MapPanel {
+    pub fn new(document: &Document, rows: &[RocketData], pad_latitude: f64, pad_longitude: f64) -> Result<MapPanel, JsValue> {
+        let canvas = document
+            .get_element_by_id("map")
+            .ok_or_else(|| JsValue::from_str("missing map canvas"))?
+            .dyn_into::<HtmlCanvasElement>()?;
+        let context = canvas
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("no 2d context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+
+        Ok(MapPanel { canvas, context, track: ground_track(rows, pad_latitude, pad_longitude) })
+    }
+
+    // Re-centres the track after the launch site moved.
+    pub fn set_pad(&mut self, rows: &[RocketData], pad_latitude: f64, pad_longitude: f64) {
+        self.track = ground_track(rows, pad_latitude, pad_longitude);
+    }
+
+    // Draws the fixes received by `index`, with `ground_altitude` the pad altitude in the fused datum.
+    pub fn draw(&self, rows: &[RocketData], altitude: &[AltitudeEstimate], units: &DisplayUnits, index: usize, ground_altitude: f64) -> Result<(), JsValue> {
+        let (width, height) = (self.canvas.client_width().max(1) as u32, self.canvas.client_height().max(1) as u32);
+        if self.canvas.width() != width || self.canvas.height() != height {
+            self.canvas.set_width(width);
+            self.canvas.set_height(height);
+        }
+        let (width, height) = (width as f64, height as f64);
+        let c = &self.context;
+        c.clear_rect(0.0, 0.0, width, height);
+        c.set_fill_style_str("rgba(0, 0, 0, 0.55)");
+        c.fill_rect(0.0, 0.0, width, height);
+
+        let index = match rows.len() {
+            0 => return Ok(()),
+            len => index.min(len - 1),
+        };
+        let track = &self.track[..fixes_by(&self.track, index)];
+        let prediction = predict_landing(&self.track, rows, altitude, index, ground_altitude);
+
+        let reach = track.iter().map(|point| point.east.hypot(point.north))
+            .chain(prediction.iter().map(|p| p.east.hypot(p.north)))
+            .fold(0.0, f64::max);
+        let range = RANGES.iter().copied().find(|range| *range >= reach * 1.1).unwrap_or(RANGES[RANGES.len() - 1]);
+
+        let map_size = (height - PADDING - LINE_HEIGHT * TEXT_LINES as f64).min(width) - PADDING * 2.0;
+        let (center_x, center_y) = (width / 2.0, PADDING + map_size / 2.0);
+        let scale = map_size / 2.0 / range;
+        let to_screen = |east: f64, north: f64| (center_x + east * scale, center_y - north * scale);
+
+        // range rings at half and full range
+        c.set_line_width(1.0);
+        c.set_stroke_style_str("rgba(255, 255, 255, 0.25)");
+        c.set_fill_style_str("#9ab");
+        c.set_font(FONT);
+        c.set_text_baseline("bottom");
+        c.set_text_align("left");
+        for ring in [range / 2.0, range].iter() {
+            c.begin_path();
+            c.arc(center_x, center_y, ring * scale, 0.0, TAU)?;
+            c.stroke();
+            c.fill_text(&units.format(*ring, Unit::Meter, 0), center_x + 3.0, center_y - ring * scale)?;
+        }
+        c.set_text_align("center");
+        c.set_text_baseline("top");
+        c.fill_text("N", center_x, center_y - map_size / 2.0 + 2.0)?;
+
+        if let Some((first, rest)) = track.split_first() {
+            c.set_stroke_style_str("#fff");
+            c.begin_path();
+            let (x, y) = to_screen(first.east, first.north);
+            c.move_to(x, y);
+            for point in rest {
+                let (x, y) = to_screen(point.east, point.north);
+                c.line_to(x, y);
+            }
+            c.stroke();
+        }
+
+        // pad
+        c.set_fill_style_str("#fc3");
+        c.begin_path();
+        c.move_to(center_x, center_y - 5.0);
+        c.line_to(center_x + 5.0, center_y + 4.0);
+        c.line_to(center_x - 5.0, center_y + 4.0);
+        c.close_path();
+        c.fill();
+
+        let last = track.last();
+        if let (Some(fix), Some(landing)) = (last, &prediction) {
+            let (fix_x, fix_y) = to_screen(fix.east, fix.north);
+            let (x, y) = to_screen(landing.east, landing.north);
+            c.set_stroke_style_str("#f44");
+            c.begin_path();
+            c.move_to(fix_x, fix_y);
+            c.line_to(x, y);
+            c.move_to(x - 5.0, y - 5.0);
+            c.line_to(x + 5.0, y + 5.0);
+            c.move_to(x + 5.0, y - 5.0);
+            c.line_to(x - 5.0, y + 5.0);
+            c.stroke();
+        }
+        if let Some(fix) = last {
+            let (x, y) = to_screen(fix.east, fix.north);
+            c.set_fill_style_str("#4cf");
+            c.begin_path();
+            c.arc(x, y, 4.0, 0.0, TAU)?;
+            c.fill();
+        }
+
+        let from_pad = |east: f64, north: f64| {
+            let (distance, bearing) = distance_bearing(east, north);
+            format!("{} @ {:03.0}\u{b0} {}", units.format(distance, Unit::Meter, 0), bearing, compass_point(bearing))
+        };
+        let lines = [
+            ("Last fix", last.map(|fix| format!("{:.5}, {:.5}", fix.latitude, fix.longitude)).unwrap_or_else(|| "none".to_string())),
+            ("From pad", last.map(|fix| from_pad(fix.east, fix.north)).unwrap_or_default()),
+            ("Landing", prediction.map(|p| format!("{} in {:.0} s", from_pad(p.east, p.north), p.seconds)).unwrap_or_else(|| "-".to_string())),
+        ];
+        c.set_text_align("left");
+        for (i, (label, value)) in lines.iter().enumerate() {
+            let y = height - PADDING - LINE_HEIGHT * (TEXT_LINES - i) as f64;
+            c.set_fill_style_str("#9ab");
+            c.fill_text(label, PADDING, y)?;
+            c.set_fill_style_str("#fff");
+            c.fill_text(value, PADDING + 70.0, y)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use crate::units::{convert, Unit};
+
+// A flight simulation exported from OpenRocket ("Export as" CSV with the field descriptions and flight
+// events included), in SI units with the time measured from launch.
+#[derive(Clone, Debug, Default)]
+pub struct Simulation {
+    pub time: Vec<f64>,                          // s
+    pub altitude: Vec<f64>,                      // m above the launch site
+    pub vertical_velocity: Option<Vec<f64>>,     // m/s
+    pub vertical_acceleration: Option<Vec<f64>>, // m/s², without gravity
+    pub temperature: Option<Vec<f64>>,           // °C
+    pub pressure: Option<Vec<f64>>,              // hPa
+    pub events: Vec<(String, f64)>,              // name and time
+}
+
+// The columns read from the export, by OpenRocket's field names, and the unit they are stored in.
+const COLUMNS: [(&str, Unit); 6] = [
+    ("Time", Unit::Second),
+    ("Altitude", Unit::Meter),
+    ("Vertical velocity", Unit::MeterPerSecond),
+    ("Vertical acceleration", Unit::MeterPerSecondSquared),
+    ("Air temperature", Unit::Celsius),
+    ("Air pressure", Unit::Hectopascal),
+];
+
+impl Simulation {
+    // When the rocket leaves the pad, the liftoff event or else the launch event, which is time zero.
+    pub fn liftoff(&self) -> f64 {
+        self.event("LIFTOFF").or_else(|| self.event("LAUNCH")).unwrap_or(0.0)
+    }
+
+    pub fn event(&self, name: &str) -> Option<f64> {
+        self.events.iter().find(|(event, _)| event.eq_ignore_ascii_case(name)).map(|(_, time)| *time)
+    }
+
+    // Altitude at `time`, interpolated between samples and held at the first and last.
+    pub fn altitude_at(&self, time: f64) -> f64 {
+        let i = self.time.partition_point(|t| *t < time);
+        if i == 0 {
+            return self.altitude.first().copied().unwrap_or(0.0);
+        }
+        if i == self.time.len() {
+            return self.altitude.last().copied().unwrap_or(0.0);
+        }
+        let (t0, t1) = (self.time[i - 1], self.time[i]);
+        let f = if t1 > t0 { (time - t0) / (t1 - t0) } else { 1.0 };
+        self.altitude[i - 1] + (self.altitude[i] - self.altitude[i - 1]) * f
+    }
+}
+
+// Reads the header comment naming the fields, e.g. "# Time (s),Altitude (ft),Vertical velocity (ft/s)",
+// the "# Event APOGEE occurred at t=12.3 seconds" comments and the data rows. Time and altitude are
+// required, the other columns are read when present. Values OpenRocket leaves as NaN, or that are empty,
+// repeat the previous row's.
+pub fn parse_openrocket_csv(text: &str) -> Result<Simulation, String> {
+    let mut simulation = Simulation::default();
+    // column index and unit for each of COLUMNS
+    let mut fields: Vec<Option<(usize, Unit)>> = vec![None; COLUMNS.len()];
+    let mut separator = ',';
+    let mut values: Vec<Vec<f64>> = vec![Vec::new(); COLUMNS.len()];
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = comment.trim();
+            if let Some(event) = parse_event(comment) {
+                simulation.events.push(event);
+            } else if fields[0].is_none() && comment.starts_with("Time") {
+                separator = [',', ';', '\t'].iter().copied().max_by_key(|s| comment.matches(*s).count()).unwrap_or(',');
+                fields = parse_header(comment, separator)?;
+            }
+            continue;
+        }
+
+        let (time_column, _) = fields[0].ok_or_else(|| "no \"# Time (s),...\" field description before the data".to_string())?;
+        let (altitude_column, _) = fields[1].ok_or_else(|| "no Altitude column".to_string())?;
+        let cells: Vec<&str> = line.split(separator).map(|cell| cell.trim()).collect();
+        let cell = |column: usize| -> Result<f64, String> {
+            let text = cells.get(column).ok_or_else(|| format!("line {}: missing column {}", number + 1, column + 1))?;
+            // a cell left empty, e.g. by a spreadsheet, is as unknown as OpenRocket's NaN
+            if text.is_empty() {
+                return Ok(f64::NAN);
+            }
+            text.parse::<f64>().map_err(|_| format!("line {}: bad number \"{}\"", number + 1, text))
+        };
+        if cell(time_column)?.is_nan() || cell(altitude_column)?.is_nan() {
+            continue;
+        }
+        for ((field, (_, si)), column) in fields.iter().zip(COLUMNS.iter()).zip(values.iter_mut()) {
+            if let Some((index, unit)) = field {
+                let value = cell(*index)?;
+                let value = if value.is_nan() { column.last().copied().unwrap_or(0.0) } else { convert(value, *unit, *si) };
+                column.push(value);
+            }
+        }
+    }
+
+    if fields[0].is_none() {
+        return Err("no \"# Time (s),...\" field description, export with field descriptions included".to_string());
+    }
+    if values[0].is_empty() {
+        return Err("no data rows".to_string());
+    }
+    let mut columns = values.into_iter().zip(fields.iter()).map(|(column, field)| field.map(|_| column));
+    simulation.time = columns.next().flatten().unwrap_or_default();
+    simulation.altitude = columns.next().flatten().unwrap_or_default();
+    simulation.vertical_velocity = columns.next().flatten();
+    simulation.vertical_acceleration = columns.next().flatten();
+    simulation.temperature = columns.next().flatten();
+    simulation.pressure = columns.next().flatten();
+    Ok(simulation)
+}
+
+// Finds each of COLUMNS in a "Name (unit)" header.
+fn parse_header(header: &str, separator: char) -> Result<Vec<Option<(usize, Unit)>>, String> {
+    let mut fields = vec![None; COLUMNS.len()];
+    for (index, field) in header.split(separator).enumerate() {
+        let field = field.trim();
+        let (name, unit) = match (field.rfind('('), field.ends_with(')')) {
+            (Some(open), true) => (field[..open].trim(), &field[open + 1..field.len() - 1]),
+            _ => (field, ""),
+        };
+        if let Some(column) = COLUMNS.iter().position(|(column, _)| column.eq_ignore_ascii_case(name)) {
+            fields[column] = Some((index, parse_unit(unit, COLUMNS[column].1).map_err(|e| format!("{}: {}", name, e))?));
+        }
+    }
+    if fields[1].is_none() {
+        return Err("no Altitude column".to_string());
+    }
+    Ok(fields)
+}
+
+// OpenRocket's unit names where they differ from ours, checked against the quantity the column holds.
+fn parse_unit(symbol: &str, si: Unit) -> Result<Unit, String> {
+    let unit = match symbol.trim() {
+        "mbar" => Some(Unit::Hectopascal),
+        "mi/h" => Some(Unit::MilePerHour),
+        "G" => Some(Unit::StandardGravity),
+        symbol => Unit::from_symbol(symbol),
+    };
+    match unit {
+        Some(unit) if unit.quantity() == si.quantity() => Ok(unit),
+        _ => Err(format!("unsupported unit \"{}\"", symbol)),
+    }
+}
+
+// "Event APOGEE occurred at t=12.345 seconds"
+fn parse_event(comment: &str) -> Option<(String, f64)> {
+    let rest = comment.strip_prefix("Event ")?;
+    let (name, time) = rest.split_once(" occurred at t=")?;
+    let time = time.trim_end_matches("seconds").trim().parse::<f64>().ok()?;
+    Some((name.trim().to_string(), time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = "\
+# Computed with OpenRocket 23.09
+# Simulation 1 (max 1050 ft)
+# Time (s),Altitude (ft),Vertical velocity (ft/s),Air temperature (°F),Air pressure (mbar)
+# Event LAUNCH occurred at t=0 seconds
+0,0,0,59,1013.25
+# Event LIFTOFF occurred at t=0.05 seconds
+0.1,10,100,59,1013
+NaN,NaN,NaN,NaN,NaN
+0.2,40,NaN,,1012
+# Event APOGEE occurred at t=8.5 seconds
+8.5,1000,0,55.4,979
+";
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn reads_an_export_in_si_units() {
+        let simulation = parse_openrocket_csv(EXPORT).unwrap();
+        assert_eq!(simulation.time, vec![0.0, 0.1, 0.2, 8.5]);
+        assert!(close(simulation.altitude[1], 3.048) && close(simulation.altitude[3], 304.8));
+        assert!(close(simulation.vertical_velocity.as_ref().unwrap()[1], 30.48));
+        assert!(close(simulation.temperature.as_ref().unwrap()[0], 15.0));
+        assert!(close(simulation.pressure.as_ref().unwrap()[3], 979.0));
+        assert!(simulation.vertical_acceleration.is_none());
+
+        assert_eq!(simulation.events.len(), 3);
+        assert_eq!(simulation.liftoff(), 0.05);
+        assert_eq!(simulation.event("apogee"), Some(8.5));
+        assert!(close(simulation.altitude_at(0.15), (3.048 + 40.0 * 0.3048) / 2.0));
+        assert_eq!(simulation.altitude_at(20.0), simulation.altitude[3]);
+    }
+
+    #[test]
+    fn repeats_the_previous_value_for_nan_and_empty_cells() {
+        let simulation = parse_openrocket_csv(EXPORT).unwrap();
+        // the all NaN row has no time and is dropped, the next row's velocity is NaN and its temperature empty
+        assert_eq!(simulation.time.len(), 4);
+        assert!(close(simulation.vertical_velocity.as_ref().unwrap()[2], 30.48));
+        assert!(close(simulation.temperature.as_ref().unwrap()[2], 15.0));
+    }
+
+    #[test]
+    fn finds_the_header_with_any_separator() {
+        let export = "# Time (s);Air pressure (kPa);Altitude (m)\n0;101.3;0\n1;100.1;100\n";
+        let simulation = parse_openrocket_csv(export).unwrap();
+        assert_eq!(simulation.altitude, vec![0.0, 100.0]);
+        assert!(close(simulation.pressure.unwrap()[1], 1001.0));
+
+        let export = "# Time (s)\tAltitude (m)\n# Time is not a header twice\n0\t5\n";
+        assert_eq!(parse_openrocket_csv(export).unwrap().altitude, vec![5.0]);
+    }
+
+    #[test]
+    fn reports_missing_headers_columns_and_rows() {
+        let error = |text: &str| parse_openrocket_csv(text).err().unwrap();
+        assert!(error("0,0\n1,10\n").contains("field description"));
+        assert!(error("# Time (s),Vertical velocity (m/s)\n0,0\n").contains("no Altitude column"));
+        assert!(error("# Time (s),Altitude (m)\n").contains("no data rows"));
+        assert!(error("# Time (s),Altitude (m),Air pressure (Pa)\n0,0,101325\n1,10\n").contains("line 3: missing column 3"));
+        assert!(error("# Time (s),Altitude (m)\n0,high\n").contains("bad number \"high\""));
+        assert!(error("# Time (s),Altitude (m/s)\n0,0\n").contains("unsupported unit"));
+    }
+}
@@ -0,0 +1,235 @@
+use nalgebra_glm as glm;
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlTexture, WebGlVertexArrayObject};
+
+use crate::derived::sample_period_seconds;
+use crate::flight_events::{detect_events, EventKind};
+use crate::flight_state::{FlightState, StateTimeline};
+use crate::kalman::AltitudeEstimate;
+use crate::load_model::{align_z, make_box, segment, transform_mesh, Mesh};
+use crate::rocket_data::RocketData;
+use crate::terrain::enu_offset;
+use crate::webgl::{make_texture, make_vao_vert_norm_uv, ShaderProgram};
+
+// Half width of the GPS window the horizontal velocity is taken over.
+const GROUND_VELOCITY_WINDOW_SECONDS: f64 = 1.0;
+// Below this the descent direction is too noisy to follow and the canopy just streams upwards.
+const MIN_ORIENT_SPEED: f32 = 1.0;
+
+const CANOPY_GORES: usize = 12;
+const CANOPY_RINGS: usize = 6;
+// Angle from the apex to the skirt, a flattened hemisphere like an inflated round canopy.
+const CANOPY_SKIRT_DEGREES: f32 = 70.0;
+const SHROUD_LINE_LENGTH: f32 = 1.2;     // in canopy radii, skirt to confluence
+const LINE_THICKNESS: f32 = 0.04;        // m
+// Uninflated the canopy is a narrow bundle a few times longer than it is wide.
+const STREAMING_WIDTH: f32 = 0.15;
+const STREAMING_LENGTH: f32 = 2.5;
+
+// Texture coordinates into the two texel gore texture.
+const ORANGE_UV: [f32; 2] = [0.25, 0.5];
+const WHITE_UV: [f32; 2] = [0.75, 0.5];
+
+#[derive(Clone, Debug)]
+pub struct ParachuteConfig {
+    pub name: &'static str,
+    // Deploys on entering `state`, or at the detected `event` for logs that never reach that state.
+    pub state: FlightState,
+    pub event: EventKind,
+    pub canopy_radius: f32,     // m
+    pub cord_length: f32,       // m, rocket to the shroud line confluence
+    pub inflation_seconds: f64,
+}
+
+pub fn default_parachutes() -> Vec<ParachuteConfig> {
+    vec![
+        ParachuteConfig {
+            name: "drogue",
+            state: FlightState::Drogue,
+            event: EventKind::Drogue,
+            canopy_radius: 0.9,
+            cord_length: 6.0,
+            inflation_seconds: 0.6,
+        },
+        ParachuteConfig {
+            name: "main",
+            state: FlightState::Main,
+            event: EventKind::Main,
+            canopy_radius: 2.5,
+            cord_length: 9.0,
+            inflation_seconds: 1.5,
+        },
+    ]
+}
+
+// Row each parachute comes out at, from the logged state when there is one.
+fn deployment_rows(rows: &[RocketData], timeline: &StateTimeline, configs: &[ParachuteConfig]) -> Vec<Option<usize>> {
+    let events = detect_events(rows);
+    configs.iter().map(|config| {
+        timeline.first(config.state).map(|phase| phase.start_row)
+            .or_else(|| events.iter().find(|event| event.kind == config.event).map(|event| event.row))
+    }).collect()
+}
+
+// East, north, up velocity in m/s for every row. Horizontal comes from the GPS track, vertical from the
+// fused altitude.
+pub fn ground_velocity(rows: &[RocketData], altitude: &[AltitudeEstimate]) -> Vec<glm::Vec3> {
+    let window = (GROUND_VELOCITY_WINDOW_SECONDS / sample_period_seconds(rows)).round() as usize;
+    (0..rows.len()).map(|i| {
+        let (a, b) = (&rows[i.saturating_sub(window)], &rows[(i + window).min(rows.len() - 1)]);
+        let dt = b.timestamp.saturating_sub(a.timestamp) as f64 / 1000.0;
+        let (east, north) = if a.position_lock != 0 && b.position_lock != 0 && dt > 0.0 {
+            let (east, north) = enu_offset(a.latitude, a.longitude, b.latitude, b.longitude);
+            (east / dt, north / dt)
+        } else {
+            (0.0, 0.0)
+        };
+        let up = altitude.get(i).map(|e| e.velocity).unwrap_or(0.0);
+        glm::vec3(east as f32, north as f32, up as f32)
+    }).collect()
+}
+
+fn push_triangle(mesh: &mut Mesh, points: [glm::Vec3; 3], normals: [glm::Vec3; 3], uv: [f32; 2]) {
+    for (p, n) in points.iter().zip(normals.iter()) {
+        mesh.0.extend_from_slice(&[p.x, p.y, p.z]);
+        mesh.1.extend_from_slice(&[n.x, n.y, n.z]);
+        mesh.2.extend_from_slice(&uv);
+    }
+}
+
+// Unit radius dome with the skirt on z = 0 and the apex up, alternating orange and white gores, and shroud
+// lines down to the confluence on the axis. Both sides of the fabric are meshed since it is seen from
+// below as much as from above.
+fn make_canopy() -> Mesh {
+    let mut mesh: Mesh = (Vec::new(), Vec::new(), Vec::new());
+    let skirt = CANOPY_SKIRT_DEGREES.to_radians();
+    let point = |ring: usize, gore: usize| {
+        let theta = skirt * ring as f32 / CANOPY_RINGS as f32;
+        let phi = std::f32::consts::TAU * gore as f32 / CANOPY_GORES as f32;
+        let normal = glm::vec3(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+        (normal - glm::vec3(0.0, 0.0, skirt.cos()), normal)
+    };
+
+    for gore in 0..CANOPY_GORES {
+        let uv = if gore % 2 == 0 { ORANGE_UV } else { WHITE_UV };
+        for ring in 0..CANOPY_RINGS {
+            let quad = [point(ring, gore), point(ring + 1, gore), point(ring + 1, gore + 1), point(ring, gore + 1)];
+            for [a, b, c] in [[0, 1, 2], [0, 2, 3]].iter() {
+                let (pa, pb, pc) = (quad[*a], quad[*b], quad[*c]);
+                push_triangle(&mut mesh, [pa.0, pb.0, pc.0], [pa.1, pb.1, pc.1], uv);
+                push_triangle(&mut mesh, [pa.0, pc.0, pb.0], [-pa.1, -pc.1, -pb.1], uv);
+            }
+        }
+    }
+
+    let confluence = glm::vec3(0.0, 0.0, -SHROUD_LINE_LENGTH);
+    let line = make_box(&glm::vec3(-0.5, -0.5, 0.0), &glm::vec3(0.5, 0.5, 1.0), WHITE_UV);
+    for gore in 0..CANOPY_GORES {
+        let (skirt_point, _) = point(CANOPY_RINGS, gore);
+        let transform = segment(&confluence, &skirt_point) * glm::scale(&glm::identity(), &glm::vec3(0.02, 0.02, 1.0));
+        let (verts, norms, uvs) = transform_mesh(line.clone(), &transform);
+        mesh.0.extend(verts);
+        mesh.1.extend(norms);
+        mesh.2.extend(uvs);
+    }
+    mesh
+}
+
+fn smoothstep(x: f64) -> f32 {
+    let x = x.clamp(0.0, 1.0);
+    (x * x * (3.0 - 2.0 * x)) as f32
+}
+
+// Drogue and main canopies with their shock cords, drawn with the rocket program after deployment. The main
+// replaces the drogue once it is out.
+pub struct Parachutes {
+    configs: Vec<ParachuteConfig>,
+    deployment_rows: Vec<Option<usize>>,
+    velocity: Vec<glm::Vec3>,
+    canopy_vao: WebGlVertexArrayObject,
+    canopy_vertex_count: i32,
+    cord_vao: WebGlVertexArrayObject,
+    cord_vertex_count: i32,
+    texture: Option<WebGlTexture>,
+}
+
+impl Parachutes {
+    pub fn new(context: &WebGl2RenderingContext, program: &ShaderProgram, rows: &[RocketData], altitude: &[AltitudeEstimate], timeline: &StateTimeline, configs: Vec<ParachuteConfig>) -> Result<Parachutes, JsValue> {
+        let (verts, norms, uvs) = make_canopy();
+        let (canopy_vao, canopy_vertex_count) = make_vao_vert_norm_uv(context, program, &verts, &norms, &uvs)?;
+        let (verts, norms, uvs) = make_box(&glm::vec3(-0.5, -0.5, 0.0), &glm::vec3(0.5, 0.5, 1.0), WHITE_UV);
+        let (cord_vao, cord_vertex_count) = make_vao_vert_norm_uv(context, program, &verts, &norms, &uvs)?;
+        // texels are BGR, the order the rocket shader expects from bmp textures
+        let texture = make_texture(context, &[20, 90, 235, 240, 240, 240], 2, 1);
+
+        Ok(Parachutes {
+            deployment_rows: deployment_rows(rows, timeline, &configs),
+            velocity: ground_velocity(rows, altitude),
+            configs,
+            canopy_vao,
+            canopy_vertex_count,
+            cord_vao,
+            cord_vertex_count,
+            texture,
+        })
+    }
+
+    // Picks the deployment rows up again after the state mapping changed.
+    pub fn set_timeline(&mut self, rows: &[RocketData], timeline: &StateTimeline) {
+        self.deployment_rows = deployment_rows(rows, timeline, &self.configs);
+    }
+
+    pub fn set_inflation_seconds(&mut self, name: &str, seconds: f64) -> Result<(), String> {
+        let config = self.configs.iter_mut().find(|config| config.name == name).ok_or_else(|| format!("no parachute named {}", name))?;
+        config.inflation_seconds = seconds.max(0.0);
+        Ok(())
+    }
+
+    // The parachute out at `row` with the seconds since it deployed.
+    fn deployed(&self, rows: &[RocketData], row: usize) -> Option<(&ParachuteConfig, f64)> {
+        self.configs.iter().zip(self.deployment_rows.iter())
+            .filter_map(|(config, deployed)| deployed.filter(|deployed| *deployed <= row).map(|deployed| (config, deployed)))
+            .max_by_key(|(_, deployed)| *deployed)
+            .map(|(config, deployed)| (config, rows[row].timestamp.saturating_sub(rows[deployed].timestamp) as f64 / 1000.0))
+    }
+
+    // Draws the active parachute with the rocket program already in use. `attach` is the scene position
+    // the shock cord is tied to.
+    pub fn render(&self, ctx: &WebGl2RenderingContext, program: &ShaderProgram, rows: &[RocketData], row: usize, view_projection: &glm::Mat4, attach: &glm::Vec3) {
+        let row = row.min(rows.len().saturating_sub(1));
+        let (config, elapsed) = match self.deployed(rows, row) {
+            Some(deployed) => deployed,
+            None => return,
+        };
+        let inflation = if config.inflation_seconds > 0.0 { smoothstep(elapsed / config.inflation_seconds) } else { 1.0 };
+
+        // the canopy trails opposite the motion, straight up when there is too little of it to tell
+        let velocity = self.velocity.get(row).copied().unwrap_or_else(glm::zero);
+        let direction = if glm::length(&velocity) > MIN_ORIENT_SPEED && velocity.z < 0.0 {
+            -glm::normalize(&velocity)
+        } else {
+            glm::vec3(0.0, 0.0, 1.0)
+        };
+
+        let confluence = attach + direction * config.cord_length;
+        let radius = config.canopy_radius;
+        let width = radius * (STREAMING_WIDTH + (1.0 - STREAMING_WIDTH) * inflation);
+        let length = radius * (STREAMING_LENGTH + (1.0 - STREAMING_LENGTH) * inflation);
+        let orientation = align_z(&direction);
+        let canopy = glm::translate(&glm::identity(), &(confluence + direction * SHROUD_LINE_LENGTH * length))
+            * orientation
+            * glm::scale(&glm::identity(), &glm::vec3(width, width, length));
+        let cord = segment(attach, &confluence) * glm::scale(&glm::identity(), &glm::vec3(LINE_THICKNESS, LINE_THICKNESS, 1.0));
+
+        program.set_texture(ctx, "tex", self.texture.as_ref(), 0);
+        program.set_f32(ctx, "receive_shadow", 0.0);
+        program.set_f32(ctx, "opacity", 1.0);
+        for (vao, vertex_count, model) in [(&self.cord_vao, self.cord_vertex_count, cord), (&self.canopy_vao, self.canopy_vertex_count, canopy)].iter() {
+            ctx.bind_vertex_array(Some(*vao));
+            program.set_mat4(ctx, "mvp", &(view_projection * model));
+            program.set_mat4(ctx, "rotate", &orientation);
+            program.set_mat4(ctx, "light_mvp", &glm::identity());
+            ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, *vertex_count);
+        }
+    }
+}
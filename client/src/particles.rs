@@ -0,0 +1,273 @@
+use nalgebra_glm as glm;
+use wasm_bindgen::JsValue;
+use web_sys::*;
+
+use crate::flight_events::axial_acceleration;
+use crate::flight_state::{FlightState, StateTimeline};
+use crate::kalman::AltitudeEstimate;
+use crate::rocket_data::RocketData;
+use crate::shaders::*;
+use crate::webgl::*;
+
+pub const PARTICLE_SHADER: ShaderFiles = ShaderFiles { vert: "particle.vert.glsl", frag: "particle.frag.glsl" };
+
+// The motor is lit from launch detection until burnout detection.
+const BURNING_STATES: [FlightState; 2] = [FlightState::LaunchDetect, FlightState::Boost];
+const MAX_PARTICLES: usize = 4096;
+// Axial acceleration that gives the nominal emission rates, above it the plume keeps growing up to MAX_INTENSITY.
+const REFERENCE_ACCELERATION_G: f64 = 10.0;
+const MAX_INTENSITY: f64 = 2.5;
+// A longer step than this is a seek, the old plume is thrown away rather than simulated forward.
+const MAX_STEP_SECONDS: f32 = 0.5;
+// position xyz, size, premultiplied rgba
+const FLOATS_PER_INSTANCE: usize = 8;
+// Every reset starts the scatter from here, so a replay from the start draws the same plume.
+const RANDOM_SEED: u32 = 0x2545_f491;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ParticleKind {
+    Flame,
+    Smoke,
+}
+
+// Emission and motion of each kind, speeds in m/s relative to the nozzle and sizes in metres.
+struct KindParameters {
+    rate_per_second: f32,
+    lifetime_seconds: f32,
+    exhaust_speed: f32,
+    spread_speed: f32,
+    // fraction of velocity lost per second to the air
+    drag: f32,
+    start_size: f32,
+    end_size: f32,
+}
+
+impl ParticleKind {
+    fn parameters(self) -> KindParameters {
+        match self {
+            ParticleKind::Flame => KindParameters {
+                rate_per_second: 300.0,
+                lifetime_seconds: 0.1,
+                exhaust_speed: 30.0,
+                spread_speed: 2.0,
+                drag: 0.0,
+                start_size: 0.5,
+                end_size: 1.0,
+            },
+            ParticleKind::Smoke => KindParameters {
+                rate_per_second: 120.0,
+                lifetime_seconds: 6.0,
+                exhaust_speed: 15.0,
+                spread_speed: 3.0,
+                drag: 1.5,
+                start_size: 0.8,
+                end_size: 6.0,
+            },
+        }
+    }
+}
+
+struct Particle {
+    kind: ParticleKind,
+    position: glm::Vec3,
+    velocity: glm::Vec3,
+    age: f32,
+    lifetime: f32,
+    intensity: f32,
+}
+
+impl Particle {
+    // Diameter and premultiplied colour at the particle's current age. Flames have zero alpha so they add
+    // light, smoke covers what is behind it.
+    fn appearance(&self) -> (f32, glm::Vec4) {
+        let parameters = self.kind.parameters();
+        let t = (self.age / self.lifetime).clamp(0.0, 1.0);
+        match self.kind {
+            ParticleKind::Flame => {
+                let size = glm::lerp_scalar(parameters.start_size, parameters.end_size, t) * (0.5 + 0.5 * self.intensity.min(1.5));
+                let hot = glm::vec3(1.0, 0.95, 0.7);
+                let cool = glm::vec3(1.0, 0.4, 0.1);
+                let color = glm::lerp(&hot, &cool, t) * ((1.0 - t) * 0.6 * self.intensity.min(1.0));
+                (size, glm::vec4(color.x, color.y, color.z, 0.0))
+            }
+            ParticleKind::Smoke => {
+                let size = glm::lerp_scalar(parameters.start_size, parameters.end_size, t.sqrt());
+                // fades in over the first tenth so it does not pop out of the flame
+                let alpha = 0.45 * (1.0 - t) * (1.0 - t) * (t * 10.0).min(1.0);
+                let grey = 0.75 * alpha;
+                (size, glm::vec4(grey, grey, grey, alpha))
+            }
+        }
+    }
+}
+
+// xorshift, good enough to scatter particles without pulling in a crate
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    fn in_sphere(&mut self) -> glm::Vec3 {
+        loop {
+            let v = glm::vec3(self.next(), self.next(), self.next()) * 2.0 - glm::vec3(1.0, 1.0, 1.0);
+            if glm::length2(&v) <= 1.0 {
+                return v;
+            }
+        }
+    }
+}
+
+// Exhaust flame and smoke trail while the motor burns. Particles are simulated in scene space, so the smoke
+// stays where it was made as the rocket climbs away from it, and are drawn as camera facing quads with one
+// instance per particle.
+pub struct ParticleSystem {
+    pub program: ShaderProgram,
+    vao: WebGlVertexArrayObject,
+    instance_buffer: WebGlBuffer,
+    particles: Vec<Particle>,
+    instance_data: Vec<f32>,
+    // row, timestamp and nozzle position of the previous update
+    last: Option<(usize, u32, glm::Vec3)>,
+    // fractional particles owed to each kind, carried between frames so low rates still emit
+    carry: [f32; 2],
+    random: Random,
+}
+
+impl ParticleSystem {
+    pub async fn load(ctx: &WebGl2RenderingContext) -> Result<ParticleSystem, JsValue> {
+        let program = load_shader_program(ctx, &PARTICLE_SHADER).await?;
+
+        let vao = make_vao(ctx).ok_or_else(|| JsValue::from_str("Unable to create vertex array"))?;
+        ctx.bind_vertex_array(Some(&vao));
+        let corners = [-1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0];
+        if let Some(location) = program.attribute("corner") {
+            bind_shader_array(ctx, Some(&make_buffer(ctx, &corners)), location, 2);
+        }
+
+        let instance_buffer = ctx.create_buffer().ok_or_else(|| JsValue::from_str("Unable to create buffer"))?;
+        let stride = FLOATS_PER_INSTANCE as i32;
+        if let Some(location) = program.attribute("center_size") {
+            bind_instance_array(ctx, Some(&instance_buffer), location, 4, stride, 0);
+        }
+        if let Some(location) = program.attribute("color") {
+            bind_instance_array(ctx, Some(&instance_buffer), location, 4, stride, 4);
+        }
+
+        Ok(ParticleSystem {
+            program,
+            vao,
+            instance_buffer,
+            particles: Vec::new(),
+            instance_data: Vec::new(),
+            last: None,
+            carry: [0.0; 2],
+            random: Random(RANDOM_SEED),
+        })
+    }
+
+    // Empties the sky and restarts the random scatter, so what follows doesn't depend on what played before.
+    pub fn reset(&mut self) {
+        self.particles.clear();
+        self.last = None;
+        self.carry = [0.0; 2];
+        self.random = Random(RANDOM_SEED);
+    }
+
+    // Steps the simulation to `row`, emitting from `nozzle` (scene space) if the motor is burning there.
+    // Playback running backwards or jumping ahead starts from an empty sky.
+    pub fn update(&mut self, rows: &[RocketData], row: usize, timeline: &StateTimeline, estimate: &AltitudeEstimate, nozzle: &glm::Vec3) {
+        let rd = &rows[row];
+        let (dt, previous_nozzle) = match self.last {
+            Some((last_row, last_timestamp, last_nozzle)) if row >= last_row && rd.timestamp >= last_timestamp => {
+                ((rd.timestamp - last_timestamp) as f32 / 1000.0, last_nozzle)
+            }
+            _ => (f32::INFINITY, *nozzle),
+        };
+        self.last = Some((row, rd.timestamp, *nozzle));
+        if dt > MAX_STEP_SECONDS {
+            self.particles.clear();
+            return;
+        }
+
+        for particle in &mut self.particles {
+            let drag = particle.kind.parameters().drag;
+            particle.velocity *= (-drag * dt).exp();
+            particle.position += particle.velocity * dt;
+            particle.age += dt;
+        }
+        self.particles.retain(|particle| particle.age < particle.lifetime);
+
+        let burning = timeline.phase_at_row(row).is_some_and(|phase| BURNING_STATES.contains(&phase.state));
+        if !burning || dt <= 0.0 {
+            return;
+        }
+
+        let intensity = (axial_acceleration(rd) / REFERENCE_ACCELERATION_G).clamp(0.0, MAX_INTENSITY) as f32;
+        let rocket_velocity = glm::vec3(0.0, 0.0, estimate.velocity as f32);
+        for (k, kind) in [ParticleKind::Flame, ParticleKind::Smoke].iter().enumerate() {
+            let parameters = kind.parameters();
+            self.carry[k] += parameters.rate_per_second * intensity * dt;
+            let count = self.carry[k].floor();
+            self.carry[k] -= count;
+
+            for i in 0..count as usize {
+                if self.particles.len() >= MAX_PARTICLES {
+                    break;
+                }
+                // spread over the path the nozzle took this step so a fast climb leaves no gaps in the trail
+                let fraction = (i as f32 + self.random.next()) / count;
+                let age = (1.0 - fraction) * dt;
+                let velocity = rocket_velocity
+                    - glm::vec3(0.0, 0.0, parameters.exhaust_speed)
+                    + self.random.in_sphere() * parameters.spread_speed;
+                self.particles.push(Particle {
+                    kind: *kind,
+                    position: glm::lerp(&previous_nozzle, nozzle, fraction) + velocity * age,
+                    velocity,
+                    age,
+                    lifetime: parameters.lifetime_seconds * (0.7 + 0.6 * self.random.next()),
+                    intensity,
+                });
+            }
+        }
+    }
+
+    // Draws after everything opaque, testing against depth without writing it.
+    pub fn render(&mut self, ctx: &WebGl2RenderingContext, view_projection: &glm::Mat4, view: &glm::Mat4, camera_position: &glm::Vec3) {
+        if self.particles.is_empty() {
+            return;
+        }
+
+        // smoke blends in order, so the furthest goes first
+        self.particles.sort_by(|a, b| {
+            glm::distance2(&b.position, camera_position).total_cmp(&glm::distance2(&a.position, camera_position))
+        });
+        self.instance_data.clear();
+        for particle in &self.particles {
+            let (size, color) = particle.appearance();
+            self.instance_data.extend_from_slice(&[particle.position.x, particle.position.y, particle.position.z, size]);
+            self.instance_data.extend_from_slice(color.as_slice());
+        }
+        update_buffer(ctx, &self.instance_buffer, &self.instance_data);
+
+        // the rows of the view rotation are the camera axes in scene space
+        let camera_right = glm::vec3(view[(0, 0)], view[(0, 1)], view[(0, 2)]);
+        let camera_up = glm::vec3(view[(1, 0)], view[(1, 1)], view[(1, 2)]);
+
+        self.program.use_program(ctx);
+        self.program.set_mat4(ctx, "view_proj", view_projection);
+        self.program.set_vec3(ctx, "camera_right", &camera_right);
+        self.program.set_vec3(ctx, "camera_up", &camera_up);
+        ctx.depth_mask(false);
+        ctx.blend_func(WebGl2RenderingContext::ONE, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
+        ctx.bind_vertex_array(Some(&self.vao));
+        ctx.draw_arrays_instanced(WebGl2RenderingContext::TRIANGLES, 0, 6, self.particles.len() as i32);
+        ctx.blend_func_separate(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA, WebGl2RenderingContext::ONE, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
+        ctx.depth_mask(true);
+    }
+}
@@ -1,32 +1,426 @@
+use plotters::coord::Shift;
 use plotters::prelude::*;
+use plotters_canvas::CanvasBackend;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Document, HtmlCanvasElement};
 
-// This function draws x^2 when called
-// The "folders" folder is required for the image to be inserted
-fn draw_graph() -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new("folders/0.png", (640, 480)).into_drawing_area();
-    root.fill(&WHITE)?;
-    let mut chart = ChartBuilder::on(&root)
-        .caption("y=x^2", ("sans-serif", 50).into_font())
-        .margin(5)
-        .x_label_area_size(30)
-        .y_label_area_size(30)
-        .build_cartesian_2d(-1f32..1f32, -0.1f32..1f32)?;
-
-    chart.configure_mesh().draw()?;
-
-    chart
-        .draw_series(LineSeries::new(
-            (-50..=50).map(|x| x as f32 / 50.0).map(|x| (x, x * x)),
-            &RED,
-        ))?
-        .label("y = x^2")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
-
-    chart
-        .configure_series_labels()
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()?;
-
-    Ok(())
-}
\ No newline at end of file
+use crate::derived::{derive, DerivedSample};
+use crate::kalman::{fuse_altitude, AltitudeEstimate};
+use crate::flight_events::{compare_with_log, detect_events};
+use crate::flight_state::{StateMapping, StateTimeline, NAMED_STATES};
+use crate::openrocket::Simulation;
+use crate::rocket_data::RocketData;
+use crate::units::{convert, DisplayUnits, Unit};
+
+// Seconds of data shown either side of the playback cursor.
+const WINDOW_BEFORE_SECONDS: f32 = 40.0;
+const WINDOW_AFTER_SECONDS: f32 = 20.0;
+
+pub struct Series {
+    pub title: &'static str,
+    pub unit: &'static str,
+    pub values: Vec<f32>,
+    pub range: (f32, f32),
+}
+
+impl Series {
+    pub fn new(title: &'static str, unit: &'static str, values: Vec<f32>) -> Series {
+        let lo = values.iter().copied().fold(f32::MAX, f32::min);
+        let hi = values.iter().copied().fold(f32::MIN, f32::max);
+        // keep flat series (and empty logs) from collapsing the axis
+        let pad = ((hi - lo) * 0.05).max(1e-3);
+        let range = if lo <= hi { (lo - pad, hi + pad) } else { (0.0, 1.0) };
+
+        Series { title, unit, values, range }
+    }
+}
+
+// Seconds since the first row, the x axis of every chart. Rows logged before the first one, after the
+// clock stepped back, come out negative.
+pub fn flight_times(rows: &[RocketData]) -> Vec<f32> {
+    let start = rows.first().map(|rd| rd.timestamp).unwrap_or(0);
+    rows.iter().map(|rd| seconds_between(start, rd.timestamp)).collect()
+}
+
+fn seconds_between(from: u32, to: u32) -> f32 {
+    (to as i64 - from as i64) as f32 / 1000.0
+}
+
+// `values` logged in `raw`, converted to the display unit for their quantity.
+fn display_series(title: &'static str, units: &DisplayUnits, raw: Unit, values: impl Iterator<Item = f64>) -> Series {
+    let unit = units.unit_for(raw);
+    Series::new(title, unit.symbol(), values.map(|v| convert(v, raw, unit) as f32).collect())
+}
+
+pub fn acceleration_magnitude(rd: &RocketData) -> f32 {
+    (rd.ax * rd.ax + rd.ay * rd.ay + rd.az * rd.az).sqrt() as f32
+}
+
+// The charts shown next to the 3d view, in page order.
+pub fn telemetry_series(rows: &[RocketData], derived: &[DerivedSample], units: &DisplayUnits) -> Vec<Series> {
+    vec![
+        display_series("Altitude", units, Unit::Meter, rows.iter().map(|rd| rd.barometer_altitude)),
+        display_series("Vertical velocity", units, Unit::MeterPerSecond, derived.iter().map(|d| d.vertical_velocity)),
+        display_series("Acceleration", units, Unit::StandardGravity, rows.iter().map(|rd| acceleration_magnitude(rd) as f64)),
+        display_series("Pressure", units, Unit::Hectopascal, rows.iter().map(|rd| rd.pressure)),
+        display_series("Temperature", units, Unit::Celsius, rows.iter().map(|rd| rd.temperature)),
+    ]
+}
+
+// Min/max of each bucket of rows, so decimating to the chart width never drops a spike.
+fn decimate(times: &[f32], values: &[f32], rows: std::ops::Range<usize>, buckets: usize) -> Vec<(f32, f32)> {
+    let step = ((rows.end - rows.start) / buckets.max(1)).max(1);
+    let mut points = Vec::with_capacity(2 * (rows.end - rows.start) / step + 2);
+
+    let mut i = rows.start;
+    while i < rows.end {
+        let end = (i + step).min(rows.end);
+        let (mut lo, mut hi) = (i, i);
+        for j in i..end {
+            if values[j] < values[lo] { lo = j; }
+            if values[j] > values[hi] { hi = j; }
+        }
+        // keep the pair in time order so the line doesn't zig-zag backwards
+        let (first, second) = if lo < hi { (lo, hi) } else { (hi, lo) };
+        points.push((times[first], values[first]));
+        if second != first {
+            points.push((times[second], values[second]));
+        }
+        i = end;
+    }
+
+    points
+}
+
+// The same series from another flight or a simulation, with `times` already moved onto the main flight's
+// time axis. Series the source has nothing for are None.
+pub struct ChartOverlay {
+    pub times: Vec<f32>,
+    pub series: Vec<Option<Series>>,
+    pub color: RGBColor,
+}
+
+// The telemetry_series charts as predicted by a simulation. Altitude is put on the barometer datum by adding
+// `pad_altitude`, and the accelerometer reading is taken as vertical acceleration plus gravity, which holds
+// while the rocket flies close to vertical.
+pub fn simulation_series(simulation: &Simulation, units: &DisplayUnits, pad_altitude: f64) -> Vec<Option<Series>> {
+    let optional = |title, raw, values: &Option<Vec<f64>>| values.as_ref().map(|values| display_series(title, units, raw, values.iter().copied()));
+    let acceleration = simulation.vertical_acceleration.as_ref()
+        .map(|values| values.iter().map(|a| convert(*a, Unit::MeterPerSecondSquared, Unit::StandardGravity) + 1.0).map(f64::abs).collect());
+    vec![
+        Some(display_series("Altitude", units, Unit::Meter, simulation.altitude.iter().map(|h| h + pad_altitude))),
+        optional("Vertical velocity", Unit::MeterPerSecond, &simulation.vertical_velocity),
+        optional("Acceleration", Unit::StandardGravity, &acceleration),
+        optional("Pressure", Unit::Hectopascal, &simulation.pressure),
+        optional("Temperature", Unit::Celsius, &simulation.temperature),
+    ]
+}
+
+// Draws the part of `series` inside a window around row `cursor`, with the rows already played solid, the
+// rest faded, and a vertical line at the cursor. Overlays are drawn the same way in their own colour.
+pub fn draw_strip_chart<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, times: &[f32], series: &Series, cursor: usize, overlays: &[(&[f32], &Series, RGBColor)]) -> Result<(), String> {
+    if times.is_empty() {
+        return Ok(());
+    }
+    let cursor = cursor.min(times.len() - 1);
+    let now = times[cursor];
+    let (start, end) = (now - WINDOW_BEFORE_SECONDS, now + WINDOW_AFTER_SECONDS);
+    let first = times.partition_point(|t| *t < start);
+    let last = times.partition_point(|t| *t <= end);
+    let buckets = area.dim_in_pixel().0 as usize;
+    let range = overlays.iter().fold(series.range, |(lo, hi), (_, overlay, _)| (lo.min(overlay.range.0), hi.max(overlay.range.1)));
+
+    area.fill(&WHITE).map_err(|e| e.to_string())?;
+    let mut chart = ChartBuilder::on(area)
+        .caption(format!("{} ({})", series.title, series.unit), ("sans-serif", 14))
+        .margin(4)
+        .x_label_area_size(18)
+        .y_label_area_size(44)
+        .build_cartesian_2d(start..end, range.0..range.1)
+        .map_err(|e| e.to_string())?;
+
+    chart.configure_mesh()
+        .x_labels(6)
+        .y_labels(4)
+        .label_style(("sans-serif", 10))
+        .draw()
+        .map_err(|e| e.to_string())?;
+
+    for (overlay_times, overlay, color) in overlays {
+        let first = overlay_times.partition_point(|t| *t < start);
+        let last = overlay_times.partition_point(|t| *t <= end);
+        let split = overlay_times.partition_point(|t| *t <= now).clamp(first, last);
+        let upcoming = decimate(overlay_times, &overlay.values, split.saturating_sub(1).max(first)..last, buckets);
+        let played = decimate(overlay_times, &overlay.values, first..split, buckets);
+        chart.draw_series(LineSeries::new(upcoming, color.mix(0.25))).map_err(|e| e.to_string())?;
+        chart.draw_series(LineSeries::new(played, color.mix(0.8))).map_err(|e| e.to_string())?;
+    }
+
+    let played = decimate(times, &series.values, first..(cursor + 1).max(first), buckets);
+    let upcoming = decimate(times, &series.values, cursor..last.max(cursor), buckets);
+    chart.draw_series(LineSeries::new(upcoming, BLUE.mix(0.25))).map_err(|e| e.to_string())?;
+    chart.draw_series(LineSeries::new(played, BLUE)).map_err(|e| e.to_string())?;
+    chart.draw_series(std::iter::once(PathElement::new(vec![(now, range.0), (now, range.1)], RED)))
+        .map_err(|e| e.to_string())?;
+
+    area.present().map_err(|e| e.to_string())
+}
+
+// Live strip charts drawn into the side canvases "chart0", "chart1", ... one per telemetry series.
+pub struct StripCharts {
+    canvases: Vec<HtmlCanvasElement>,
+    series: Vec<Series>,
+    times: Vec<f32>,
+    overlays: Vec<ChartOverlay>,
+}
+
+impl StripCharts {
+    pub fn new(document: &Document, rows: &[RocketData], derived: &[DerivedSample], units: &DisplayUnits) -> Result<StripCharts, JsValue> {
+        let series = telemetry_series(rows, derived, units);
+        let mut canvases = Vec::new();
+        for i in 0..series.len() {
+            let canvas = document
+                .get_element_by_id(&format!("chart{}", i))
+                .ok_or_else(|| JsValue::from_str(&format!("missing chart{} canvas", i)))?
+                .dyn_into::<HtmlCanvasElement>()?;
+            canvases.push(canvas);
+        }
+
+        Ok(StripCharts {
+            canvases,
+            series,
+            times: flight_times(rows),
+            overlays: Vec::new(),
+        })
+    }
+
+    // Adds another flight's curves, `time_shift` seconds later on the x axis than its own `flight_times`.
+    pub fn add_overlay(&mut self, rows: &[RocketData], derived: &[DerivedSample], units: &DisplayUnits, time_shift: f32, color: RGBColor) {
+        self.overlays.push(ChartOverlay {
+            times: flight_times(rows).iter().map(|t| t + time_shift).collect(),
+            series: telemetry_series(rows, derived, units).into_iter().map(Some).collect(),
+            color,
+        });
+    }
+
+    // Adds a simulation's curves, `time_shift` seconds later on the x axis than its launch.
+    pub fn add_simulation(&mut self, simulation: &Simulation, units: &DisplayUnits, time_shift: f32, pad_altitude: f64, color: RGBColor) {
+        self.overlays.push(ChartOverlay {
+            times: simulation.time.iter().map(|t| *t as f32 + time_shift).collect(),
+            series: simulation_series(simulation, units, pad_altitude),
+            color,
+        });
+    }
+
+    pub fn draw(&self, cursor: usize) -> Result<(), JsValue> {
+        for (i, (canvas, series)) in self.canvases.iter().zip(self.series.iter()).enumerate() {
+            // follow css resizes, the backing store would otherwise be stretched
+            let (width, height) = (canvas.client_width().max(1) as u32, canvas.client_height().max(1) as u32);
+            if canvas.width() != width || canvas.height() != height {
+                canvas.set_width(width);
+                canvas.set_height(height);
+            }
+
+            let area = CanvasBackend::with_canvas_object(canvas.clone())
+                .ok_or_else(|| JsValue::from_str("strip chart canvas has no 2d context"))?
+                .into_drawing_area();
+            let overlays: Vec<_> = self.overlays.iter()
+                .filter_map(|overlay| overlay.series[i].as_ref().map(|series| (overlay.times.as_slice(), series, overlay.color)))
+                .collect();
+            draw_strip_chart(&area, &self.times, series, cursor, &overlays).map_err(|e| JsValue::from_str(&e))?;
+        }
+        Ok(())
+    }
+}
+
+// Full-flight plots for post-flight review, one chart per plot with a line per named series. With `y_labels`
+// the values are indices into it and the y axis is labelled with the names instead of numbers.
+pub fn draw_time_series<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, title: &str, times: &[f32], lines: &[Series], y_labels: &[&str]) -> Result<(), String> {
+    let x_range = times.first().copied().unwrap_or(0.0)..times.last().copied().unwrap_or(1.0);
+    let lo = lines.iter().map(|s| s.range.0).fold(f32::MAX, f32::min);
+    let hi = lines.iter().map(|s| s.range.1).fold(f32::MIN, f32::max);
+    let buckets = area.dim_in_pixel().0 as usize;
+
+    area.fill(&WHITE).map_err(|e| e.to_string())?;
+    let mut chart = ChartBuilder::on(area)
+        .caption(title, ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(36)
+        .y_label_area_size(60.max(y_labels.iter().map(|label| label.len() as u32 * 8 + 30).max().unwrap_or(0)))
+        .build_cartesian_2d(x_range, lo..hi)
+        .map_err(|e| e.to_string())?;
+
+    let y_label = |value: &f32| {
+        let index = value.round();
+        match y_labels.get(index as usize) {
+            Some(label) if index >= 0.0 && (value - index).abs() < 1e-3 => label.to_string(),
+            _ if y_labels.is_empty() => format!("{}", value),
+            _ => String::new(),
+        }
+    };
+    let mut mesh = chart.configure_mesh();
+    mesh.x_desc("time (s)")
+        .y_desc(lines.first().map(|s| s.unit).unwrap_or(""))
+        .y_label_formatter(&y_label);
+    if !y_labels.is_empty() {
+        // one tick per whole number so every name can land on its line
+        mesh.y_labels(((hi - lo).ceil() as usize + 1).max(2));
+    }
+    mesh.draw().map_err(|e| e.to_string())?;
+
+    for (i, series) in lines.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        let points = decimate(times, &series.values, 0..times.len(), buckets);
+        chart.draw_series(LineSeries::new(points, color))
+            .map_err(|e| e.to_string())?
+            .label(series.title)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+
+    chart.configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .map_err(|e| e.to_string())?;
+
+    area.present().map_err(|e| e.to_string())
+}
+
+// The standard plot set of the flight report, as (file name, title, series, y axis labels) where the labels
+// are empty for plain numbers. The state timeline plots each row's mapped FlightState in flight order.
+#[allow(clippy::type_complexity)]
+pub fn report_plots(rows: &[RocketData], derived: &[DerivedSample], altitude: &[AltitudeEstimate], mapping: &StateMapping, units: &DisplayUnits) -> Vec<(&'static str, &'static str, Vec<Series>, Vec<&'static str>)> {
+    let column = |title, raw, f: fn(&RocketData) -> f64| display_series(title, units, raw, rows.iter().map(f));
+    let derived_column = |title, raw, f: fn(&DerivedSample) -> f64| display_series(title, units, raw, derived.iter().map(f));
+
+    vec![
+        ("altitude", "Altitude", vec![
+            column("barometer", Unit::Meter, |rd| rd.barometer_altitude),
+            column("gps", Unit::Meter, |rd| rd.altitude),
+            display_series("fused", units, Unit::Meter, altitude.iter().map(|e| e.altitude)),
+        ], Vec::new()),
+        ("velocity", "Vertical velocity", vec![
+            derived_column("vertical velocity", Unit::MeterPerSecond, |d| d.vertical_velocity),
+        ], Vec::new()),
+        ("mach", "Mach number", vec![
+            derived_column("mach", Unit::None, |d| d.mach),
+        ], Vec::new()),
+        ("dynamic_pressure", "Dynamic pressure", vec![
+            derived_column("q", Unit::Pascal, |d| d.dynamic_pressure),
+        ], Vec::new()),
+        ("acceleration", "Acceleration", vec![
+            column("ax", Unit::StandardGravity, |rd| rd.ax),
+            column("ay", Unit::StandardGravity, |rd| rd.ay),
+            column("az", Unit::StandardGravity, |rd| rd.az),
+        ], Vec::new()),
+        ("gyro", "Gyro rates", vec![
+            column("gx", Unit::DegreePerSecond, |rd| rd.gx),
+            column("gy", Unit::DegreePerSecond, |rd| rd.gy),
+            column("gz", Unit::DegreePerSecond, |rd| rd.gz),
+        ], Vec::new()),
+        ("extension", "Flap extension", vec![
+            column("l1_extension", Unit::None, |rd| rd.l1_extension),
+            column("l2_extension", Unit::None, |rd| rd.l2_extension),
+        ], Vec::new()),
+        ("state", "State timeline", vec![state_series(rows, mapping)], state_labels()),
+    ]
+}
+
+// Each row's state as its FlightState::ordinal, on an axis holding every state so plots line up.
+fn state_series(rows: &[RocketData], mapping: &StateMapping) -> Series {
+    let values = rows.iter().map(|rd| mapping.state(rd.rocket_state).ordinal() as f32).collect();
+    Series { range: (-0.5, NAMED_STATES.len() as f32 + 0.5), ..Series::new("rocket_state", "state", values) }
+}
+
+// Labels for state_series, indexed by ordinal.
+fn state_labels() -> Vec<&'static str> {
+    NAMED_STATES.iter().map(|state| state.name()).chain(std::iter::once("unknown")).collect()
+}
+
+pub fn flight_summary(rows: &[RocketData], mapping: &StateMapping, units: &DisplayUnits) -> String {
+    let times = flight_times(rows);
+    let derived = derive(rows);
+    let velocity: Vec<f32> = derived.iter().map(|d| d.vertical_velocity as f32).collect();
+    let mach: Vec<f32> = derived.iter().map(|d| d.mach as f32).collect();
+    let dynamic_pressure: Vec<f32> = derived.iter().map(|d| d.dynamic_pressure as f32).collect();
+    // row and value of the largest finite value, None for an empty log
+    let peak = |values: &[f32]| values.iter().copied().enumerate().filter(|(_, v)| v.is_finite())
+        .fold(None, |best: Option<(usize, f32)>, (i, v)| match best {
+            Some(b) if b.1 >= v => Some(b),
+            _ => Some((i, v)),
+        });
+    let at = |peak: Option<(usize, f32)>, format: &dyn Fn(f32) -> String| match peak {
+        Some((row, value)) => format!("{} at {:.2} s", format(value), times.get(row).copied().unwrap_or(0.0)),
+        None => "n/a".to_string(),
+    };
+
+    let altitude: Vec<f32> = rows.iter().map(|rd| rd.barometer_altitude as f32).collect();
+    let acceleration: Vec<f32> = rows.iter().map(acceleration_magnitude).collect();
+    let ground = altitude.first().copied().unwrap_or(0.0);
+
+    let mut summary = String::new();
+    summary += &format!("rows: {}\n", rows.len());
+    summary += &format!("duration: {:.2} s\n", times.last().copied().unwrap_or(0.0));
+    summary += &format!("apogee: {}\n", at(peak(&altitude), &|apogee| format!("{} ({} above the first row)",
+        units.format(apogee as f64, Unit::Meter, 1), units.format((apogee - ground) as f64, Unit::Meter, 1))));
+    summary += &format!("max vertical velocity: {}\n", at(peak(&velocity), &|v| units.format(v as f64, Unit::MeterPerSecond, 1)));
+    summary += &format!("max mach: {}\n", at(peak(&mach), &|m| format!("{:.2}", m)));
+    summary += &format!("max q: {}\n", at(peak(&dynamic_pressure), &|q| units.format(q as f64, Unit::Pascal, 1)));
+    summary += &format!("max acceleration: {}\n", at(peak(&acceleration), &|a| units.format(a as f64, Unit::StandardGravity, 2)));
+    summary += "state transitions:\n";
+    let start = rows.first().map(|rd| rd.timestamp).unwrap_or(0);
+    let timeline = StateTimeline::from_log(rows, mapping);
+    for phase in &timeline.phases {
+        summary += &format!("  {:8.2} s  {} (code {})\n", seconds_between(start, phase.start_timestamp), phase.state, rows[phase.start_row].rocket_state);
+    }
+
+    let events = detect_events(rows);
+    summary += "detected events (vs logged state):\n";
+    for comparison in compare_with_log(&events, &timeline) {
+        let seconds = |timestamp: Option<u32>| timestamp.map(|t| format!("{:8.2} s", seconds_between(start, t))).unwrap_or_else(|| "       -  ".to_string());
+        let confidence = events.iter().find(|e| e.kind == comparison.kind).map(|e| format!("{:.2}", e.confidence)).unwrap_or_else(|| "-".to_string());
+        let difference = comparison.difference_ms.map(|d| format!("{:+.2} s", d as f32 / 1000.0)).unwrap_or_else(|| "-".to_string());
+        summary += &format!("  {:8} {}  confidence {:4}  logged {}  difference {}\n",
+            comparison.kind.name(), seconds(comparison.detected), confidence, seconds(comparison.logged), difference);
+    }
+    summary
+}
+
+// Writes every report plot as png and svg plus summary.txt into `out_dir`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_flight_report(rows: &[RocketData], mapping: &StateMapping, units: &DisplayUnits, out_dir: &std::path::Path) -> Result<(), String> {
+    const PLOT_SIZE: (u32, u32) = (1280, 720);
+
+    std::fs::create_dir_all(out_dir).map_err(|e| format!("{}: {}", out_dir.display(), e))?;
+    let times = flight_times(rows);
+
+    for (name, title, lines, y_labels) in report_plots(rows, &derive(rows), &fuse_altitude(rows), mapping, units) {
+        let png = out_dir.join(format!("{}.png", name));
+        draw_time_series(&BitMapBackend::new(&png, PLOT_SIZE).into_drawing_area(), title, &times, &lines, &y_labels)?;
+        let svg = out_dir.join(format!("{}.svg", name));
+        draw_time_series(&SVGBackend::new(&svg, PLOT_SIZE).into_drawing_area(), title, &times, &lines, &y_labels)?;
+    }
+
+    let summary = out_dir.join("summary.txt");
+    std::fs::write(&summary, flight_summary(rows, mapping, units)).map_err(|e| format!("{}: {}", summary.display(), e))?;
+
+    let validation = out_dir.join("validation.json");
+    std::fs::write(&validation, crate::validation::validate(rows).to_json()).map_err(|e| format!("{}: {}", validation.display(), e))?;
+
+    let wind = out_dir.join("wind_profile.csv");
+    let bands = crate::wind::estimate_wind(rows, &fuse_altitude(rows), crate::wind::WIND_BAND_METERS);
+    std::fs::write(&wind, crate::wind::wind_profile_csv(&bands)).map_err(|e| format!("{}: {}", wind.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_log_summary_has_no_peaks() {
+        let summary = flight_summary(&[], &StateMapping::default(), &DisplayUnits::default());
+        for line in ["apogee: n/a", "max vertical velocity: n/a", "max mach: n/a", "max q: n/a", "max acceleration: n/a"] {
+            assert!(summary.contains(line), "{} missing from\n{}", line, summary);
+        }
+    }
+}
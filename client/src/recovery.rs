@@ -0,0 +1,93 @@
+use crate::kalman::AltitudeEstimate;
+use crate::rocket_data::RocketData;
+use crate::terrain::enu_offset;
+
+// Span of GPS fixes the drift under canopy is measured over. The logged fixes are only good to about 10 m,
+// so a short span turns quantisation into metres per second of drift.
+pub const DRIFT_WINDOW_SECONDS: f64 = 10.0;
+// Slower than this the rocket is treated as not coming down and no landing point is predicted.
+pub const MIN_DESCENT_RATE: f64 = 1.0; // m/s
+
+const COMPASS_POINTS: [&str; 16] = ["N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW", "NNW"];
+
+// A GPS fix that differs from the one before it, placed east and north of the pad in metres.
+#[derive(Clone, Copy, Debug)]
+pub struct TrackPoint {
+    pub row: usize,
+    pub timestamp: u32,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LandingPrediction {
+    pub east: f64,
+    pub north: f64,
+    pub seconds: f64, // until touchdown
+}
+
+// The locked fixes in the log, skipping the rows that repeat the previous fix because the logger runs
+// faster than the receiver.
+pub fn ground_track(rows: &[RocketData], pad_latitude: f64, pad_longitude: f64) -> Vec<TrackPoint> {
+    let mut track: Vec<TrackPoint> = Vec::new();
+    for (row, rd) in rows.iter().enumerate().filter(|(_, rd)| rd.position_lock != 0) {
+        if track.last().is_some_and(|last| last.latitude == rd.latitude && last.longitude == rd.longitude) {
+            continue;
+        }
+        let (east, north) = enu_offset(pad_latitude, pad_longitude, rd.latitude, rd.longitude);
+        track.push(TrackPoint { row, timestamp: rd.timestamp, latitude: rd.latitude, longitude: rd.longitude, east, north });
+    }
+    track
+}
+
+// Number of track points received by `row`.
+pub fn fixes_by(track: &[TrackPoint], row: usize) -> usize {
+    track.partition_point(|point| point.row <= row)
+}
+
+// Where the rocket comes down if it keeps its current descent rate and the drift of the last few seconds.
+// `ground_altitude` is the pad altitude in the same datum as `altitude`.
+pub fn predict_landing(track: &[TrackPoint], rows: &[RocketData], altitude: &[AltitudeEstimate], row: usize, ground_altitude: f64) -> Option<LandingPrediction> {
+    let estimate = altitude.get(row)?;
+    if estimate.velocity > -MIN_DESCENT_RATE {
+        return None;
+    }
+    let received = &track[..fixes_by(track, row)];
+    let fix = received.last()?;
+
+    let window_start = fix.timestamp.saturating_sub((DRIFT_WINDOW_SECONDS * 1000.0) as u32);
+    // walk back over the window, a fix stamped at or after the one following it ends the drift there
+    let mut earlier = fix;
+    for point in received.iter().rev().skip(1) {
+        if point.timestamp >= earlier.timestamp || point.timestamp < window_start {
+            break;
+        }
+        earlier = point;
+    }
+    let span = fix.timestamp.saturating_sub(earlier.timestamp) as f64 / 1000.0;
+    let (drift_east, drift_north) = if span > 0.0 {
+        ((fix.east - earlier.east) / span, (fix.north - earlier.north) / span)
+    } else {
+        (0.0, 0.0)
+    };
+
+    let seconds = (estimate.altitude - ground_altitude).max(0.0) / -estimate.velocity;
+    // the fix is older than the altitude estimate, so drift covers that gap too
+    let since_fix = rows[row].timestamp.saturating_sub(fix.timestamp) as f64 / 1000.0;
+    Some(LandingPrediction {
+        east: fix.east + drift_east * (since_fix + seconds),
+        north: fix.north + drift_north * (since_fix + seconds),
+        seconds,
+    })
+}
+
+// Ground distance in metres and bearing in degrees clockwise from north.
+pub fn distance_bearing(east: f64, north: f64) -> (f64, f64) {
+    (east.hypot(north), east.atan2(north).to_degrees().rem_euclid(360.0))
+}
+
+pub fn compass_point(bearing: f64) -> &'static str {
+    COMPASS_POINTS[((bearing / 22.5).round() as usize) % COMPASS_POINTS.len()]
+}
@@ -1,26 +1,29 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use crate::units::Unit;
 
-#[derive(PartialEq, Debug, PartialOrd, Deserialize)]
+pub type Column = fn(&RocketData) -> f64;
+
+#[derive(Clone, PartialEq, Debug, PartialOrd, Deserialize, Serialize)]
 pub struct RocketData {
-    pub timestamp: u32,
-    pub ax: f64,
-    pub ay: f64,
-    pub az: f64,
-    pub gx: f64,
-    pub gy: f64,
-    pub gz: f64,
-    pub mx: f64,
-    pub my: f64,
-    pub mz: f64,
-    pub latitude: f64,
-    pub longitude: f64,
-    pub altitude: f64,
+    pub timestamp: u32,             // ms
+    pub ax: f64,                    // g
+    pub ay: f64,                    // g
+    pub az: f64,                    // g
+    pub gx: f64,                    // deg/s
+    pub gy: f64,                    // deg/s
+    pub gz: f64,                    // deg/s
+    pub mx: f64,                    // gauss
+    pub my: f64,                    // gauss
+    pub mz: f64,                    // gauss
+    pub latitude: f64,              // deg
+    pub longitude: f64,             // deg
+    pub altitude: f64,              // m, GPS
     pub satellite_count: u32,
     pub position_lock: u32,
-    pub temperature: f64,
-    pub pressure: f64,
-    pub barometer_altitude: f64,
+    pub temperature: f64,           // C
+    pub pressure: f64,              // hPa
+    pub barometer_altitude: f64,    // m
     pub rocket_state: u32,
     pub l1_extension: f64,
     pub l2_extension: f64,
@@ -53,3 +56,32 @@ impl Default for RocketData {
         };
     }
 }
+
+pub fn column_unit(name: &str) -> Option<Unit> {
+    COLUMNS.iter().find(|(column, _, _)| *column == name).map(|(_, _, unit)| *unit)
+}
+
+// Every logged column by name, as f64, with the unit it is logged in.
+pub const COLUMNS: [(&str, Column, Unit); 21] = [
+    ("timestamp", |rd| rd.timestamp as f64, Unit::Millisecond),
+    ("ax", |rd| rd.ax, Unit::StandardGravity),
+    ("ay", |rd| rd.ay, Unit::StandardGravity),
+    ("az", |rd| rd.az, Unit::StandardGravity),
+    ("gx", |rd| rd.gx, Unit::DegreePerSecond),
+    ("gy", |rd| rd.gy, Unit::DegreePerSecond),
+    ("gz", |rd| rd.gz, Unit::DegreePerSecond),
+    ("mx", |rd| rd.mx, Unit::Gauss),
+    ("my", |rd| rd.my, Unit::Gauss),
+    ("mz", |rd| rd.mz, Unit::Gauss),
+    ("latitude", |rd| rd.latitude, Unit::Degree),
+    ("longitude", |rd| rd.longitude, Unit::Degree),
+    ("altitude", |rd| rd.altitude, Unit::Meter),
+    ("satellite_count", |rd| rd.satellite_count as f64, Unit::None),
+    ("position_lock", |rd| rd.position_lock as f64, Unit::None),
+    ("temperature", |rd| rd.temperature, Unit::Celsius),
+    ("pressure", |rd| rd.pressure, Unit::Hectopascal),
+    ("barometer_altitude", |rd| rd.barometer_altitude, Unit::Meter),
+    ("rocket_state", |rd| rd.rocket_state as f64, Unit::None),
+    ("l1_extension", |rd| rd.l1_extension, Unit::None),
+    ("l2_extension", |rd| rd.l2_extension, Unit::None),
+];
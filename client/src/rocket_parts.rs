@@ -0,0 +1,156 @@
+use nalgebra_glm as glm;
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlVertexArrayObject};
+
+use crate::load_model::{make_box, mesh_bounds, transform_mesh, Mesh};
+use crate::rocket_data::{Column, RocketData, COLUMNS};
+use crate::webgl::{make_vao_vert_norm_uv, ShaderProgram};
+
+// Airbrake flaps on the first stage of the Ares I model, in model units (z up, base at 0). The flaps sit
+// flush with the body and hinge at their top edge, so the bottom edge swings out into the flow.
+const BODY_RADIUS: f32 = 1.8865;
+const AIRBRAKE_BOTTOM: f32 = 9.0;
+const AIRBRAKE_TOP: f32 = 14.0;
+const AIRBRAKE_WIDTH: f32 = 1.6;
+const AIRBRAKE_THICKNESS: f32 = 0.08;
+const AIRBRAKE_MAX_ANGLE_DEGREES: f32 = 60.0;
+// `l1_extension` and `l2_extension` are logged as a fraction of full travel.
+const AIRBRAKE_FULL_EXTENSION: f64 = 1.0;
+// A plain stretch of the first stage in ares_I.bmp, so the flaps match the body.
+const AIRBRAKE_UV: [f32; 2] = [0.78, 0.27];
+
+// How a part moves away from its rest position at full deployment. Everything is in rocket model space.
+#[derive(Clone, Debug)]
+pub enum PartMotion {
+    Hinge { pivot: glm::Vec3, axis: glm::Vec3, max_angle: f32 },
+}
+
+// A named part of the rocket driven by one telemetry column. The channel reading is divided by
+// `full_scale` and clamped to 0..1 to get the deployment.
+#[derive(Clone, Debug)]
+pub struct PartDefinition {
+    pub name: String,
+    pub channel: &'static str,
+    pub full_scale: f64,
+    pub motion: PartMotion,
+}
+
+impl PartDefinition {
+    pub fn deployment(&self, value: f64) -> f32 {
+        if self.full_scale == 0.0 {
+            return 0.0;
+        }
+        (value / self.full_scale).clamp(0.0, 1.0) as f32
+    }
+
+    // Model space transform of the part at `deployment` (0 = rest, 1 = fully deployed).
+    pub fn transform(&self, deployment: f32) -> glm::Mat4 {
+        match &self.motion {
+            PartMotion::Hinge { pivot, axis, max_angle } => {
+                glm::translate(&glm::identity(), pivot)
+                    * glm::rotate(&glm::identity(), max_angle * deployment, axis)
+                    * glm::translate(&glm::identity(), &-pivot)
+            }
+        }
+    }
+}
+
+pub struct MovablePart {
+    pub definition: PartDefinition,
+    pub vao: WebGlVertexArrayObject,
+    pub vertex_count: i32,
+    channel: Column,
+    rest_bounds: (glm::Vec3, glm::Vec3),
+}
+
+impl MovablePart {
+    pub fn transform(&self, rd: &RocketData) -> glm::Mat4 {
+        self.definition.transform(self.definition.deployment((self.channel)(rd)))
+    }
+}
+
+// The movable parts drawn on top of the rigid rocket mesh, each with its own vao.
+pub struct RocketParts {
+    pub parts: Vec<MovablePart>,
+}
+
+impl RocketParts {
+    pub fn new(context: &WebGl2RenderingContext, program: &ShaderProgram, parts: Vec<(PartDefinition, Mesh)>) -> Result<RocketParts, JsValue> {
+        let mut movable = Vec::new();
+        for (definition, (verts, norms, uvs)) in parts {
+            let channel = COLUMNS.iter()
+                .find(|(name, _, _)| *name == definition.channel)
+                .map(|(_, column, _)| *column)
+                .ok_or_else(|| JsValue::from_str(&format!("{}: unknown channel {}", definition.name, definition.channel)))?;
+            let rest_bounds = mesh_bounds(&verts);
+            let (vao, vertex_count) = make_vao_vert_norm_uv(context, program, &verts, &norms, &uvs)?;
+            movable.push(MovablePart { definition, vao, vertex_count, channel, rest_bounds });
+        }
+        Ok(RocketParts { parts: movable })
+    }
+
+    // Box around every part at rest and fully deployed, for fitting the shadow camera.
+    pub fn bounds(&self) -> Option<(glm::Vec3, glm::Vec3)> {
+        let corners = self.parts.iter().flat_map(|part| {
+            let (min, max) = part.rest_bounds;
+            let deployed = part.definition.transform(1.0);
+            (0..16).map(move |i| {
+                let corner = glm::vec4(
+                    if i & 1 == 0 { min.x } else { max.x },
+                    if i & 2 == 0 { min.y } else { max.y },
+                    if i & 4 == 0 { min.z } else { max.z },
+                    1.0,
+                );
+                if i & 8 == 0 { corner.xyz() } else { (deployed * corner).xyz() }
+            })
+        });
+        corners.fold(None, |bounds, corner| match bounds {
+            None => Some((corner, corner)),
+            Some((min, max)) => Some((glm::min2(&min, &corner), glm::max2(&max, &corner))),
+        })
+    }
+
+    // Draws every part with the rocket program, which must already be in use with the texture bound.
+    // `mvp`, `light_mvp` and `rotate` are the uniforms the rigid mesh was drawn with.
+    pub fn render(&self, ctx: &WebGl2RenderingContext, program: &ShaderProgram, rd: &RocketData, mvp: &glm::Mat4, light_mvp: &glm::Mat4, rotate: &glm::Mat4) {
+        for part in &self.parts {
+            let transform = part.transform(rd);
+            ctx.bind_vertex_array(Some(&part.vao));
+            program.set_mat4(ctx, "mvp", &(mvp * transform));
+            program.set_mat4(ctx, "rotate", &(rotate * transform));
+            program.set_mat4(ctx, "light_mvp", &(light_mvp * transform));
+            ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, part.vertex_count);
+        }
+    }
+}
+
+// Four airbrake flaps, `l1_extension` driving the pair on the x axis and `l2_extension` the pair on y.
+pub fn airbrake_parts() -> Vec<(PartDefinition, Mesh)> {
+    let flap = make_box(
+        &glm::vec3(BODY_RADIUS, -AIRBRAKE_WIDTH / 2.0, AIRBRAKE_BOTTOM),
+        &glm::vec3(BODY_RADIUS + AIRBRAKE_THICKNESS, AIRBRAKE_WIDTH / 2.0, AIRBRAKE_TOP),
+        AIRBRAKE_UV,
+    );
+
+    [("l1_extension", 0.0f32), ("l2_extension", 90.0), ("l1_extension", 180.0), ("l2_extension", 270.0)].iter()
+        .map(|(channel, degrees)| {
+            let angle = degrees.to_radians();
+            let placement = glm::rotate(&glm::identity(), angle, &glm::vec3(0.0, 0.0, 1.0));
+            let radial = glm::vec3(angle.cos(), angle.sin(), 0.0);
+            let tangent = glm::vec3(-angle.sin(), angle.cos(), 0.0);
+
+            // turning about -tangent through the top edge moves the bottom edge outwards
+            let definition = PartDefinition {
+                name: format!("airbrake {}", degrees),
+                channel,
+                full_scale: AIRBRAKE_FULL_EXTENSION,
+                motion: PartMotion::Hinge {
+                    pivot: radial * BODY_RADIUS + glm::vec3(0.0, 0.0, AIRBRAKE_TOP),
+                    axis: -tangent,
+                    max_angle: AIRBRAKE_MAX_ANGLE_DEGREES.to_radians(),
+                },
+            };
+            (definition, transform_mesh(flap.clone(), &placement))
+        })
+        .collect()
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::JsValue;
+use web_sys::*;
+
+use crate::load_model::load_file;
+use crate::webgl::ShaderProgram;
+
+const SHADER_DIR: &str = "shaders";
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+pub struct ShaderFiles {
+    pub vert: &'static str,
+    pub frag: &'static str,
+}
+
+// GLSL for one program after `#include` expansion. Expanded files are separated by `#line` directives, so
+// the source string numbers in a compile log index into `files`.
+pub struct ShaderSources {
+    pub vert: String,
+    pub frag: String,
+    pub vert_files: Vec<String>,
+    pub frag_files: Vec<String>,
+}
+
+pub async fn load_shader_sources(files: &ShaderFiles) -> Result<ShaderSources, JsValue> {
+    let mut loaded = HashMap::new();
+    fetch_with_includes(files.vert, &mut loaded).await?;
+    fetch_with_includes(files.frag, &mut loaded).await?;
+
+    let mut vert_files = Vec::new();
+    let vert = preprocess(files.vert, &loaded, &mut vert_files, &mut Vec::new())?;
+    let mut frag_files = Vec::new();
+    let frag = preprocess(files.frag, &loaded, &mut frag_files, &mut Vec::new())?;
+
+    Ok(ShaderSources { vert, frag, vert_files, frag_files })
+}
+
+pub async fn load_shader_program(ctx: &WebGl2RenderingContext, files: &ShaderFiles) -> Result<ShaderProgram, JsValue> {
+    let sources = load_shader_sources(files).await?;
+    Ok(compile_shader_sources(ctx, &sources)?)
+}
+
+pub fn compile_shader_sources(ctx: &WebGl2RenderingContext, sources: &ShaderSources) -> Result<ShaderProgram, String> {
+    ShaderProgram::new(ctx, &sources.vert, &sources.frag).map_err(|log| {
+        format!("{}\nsource strings: vert {:?}, frag {:?}", log.trim_end(), sources.vert_files, sources.frag_files)
+    })
+}
+
+async fn fetch_with_includes(name: &str, loaded: &mut HashMap<String, String>) -> Result<(), JsValue> {
+    let mut pending = vec![name.to_string()];
+
+    while let Some(name) = pending.pop() {
+        if loaded.contains_key(&name) {
+            continue;
+        }
+        let bytes = load_file(&format!("{}/{}", SHADER_DIR, name)).await?;
+        let text = String::from_utf8(bytes).map_err(|_| JsValue::from_str(&format!("{} is not utf-8", name)))?;
+        pending.extend(text.lines().filter_map(parse_include));
+        loaded.insert(name, text);
+    }
+
+    Ok(())
+}
+
+fn parse_include(line: &str) -> Option<String> {
+    let rest = line.trim().strip_prefix("#include")?.trim();
+    let name = rest.strip_prefix('"')?.strip_suffix('"')?;
+    Some(name.to_string())
+}
+
+// Splices every `#include "file"` in place. `files` collects the file behind each source string number and
+// `stack` holds the chain of files currently being expanded, to report include cycles.
+fn preprocess(name: &str, loaded: &HashMap<String, String>, files: &mut Vec<String>, stack: &mut Vec<String>) -> Result<String, String> {
+    if stack.iter().any(|open| open == name) || stack.len() >= MAX_INCLUDE_DEPTH {
+        return Err(format!("include cycle: {} -> {}", stack.join(" -> "), name));
+    }
+    let text = loaded.get(name).ok_or_else(|| format!("missing shader file {}", name))?;
+
+    let index = files.len();
+    files.push(name.to_string());
+    stack.push(name.to_string());
+
+    let mut out = String::new();
+    for (line_number, line) in text.lines().enumerate() {
+        match parse_include(line) {
+            Some(include) => {
+                let included_index = files.len();
+                let included = preprocess(&include, loaded, files, stack)?;
+                out.push_str(&format!("#line 1 {}\n", included_index));
+                out.push_str(&included);
+                // the directive names the line that follows it
+                out.push_str(&format!("#line {} {}\n", line_number + 2, index));
+            }
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+
+    stack.pop();
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(sources: &[(&str, &str)]) -> HashMap<String, String> {
+        sources.iter().map(|(name, text)| (name.to_string(), text.to_string())).collect()
+    }
+
+    #[test]
+    fn expands_nested_includes_with_line_directives() {
+        let loaded = files(&[
+            ("main.frag", "#version 300 es\n#include \"light.glsl\"\nvoid main() {}\n"),
+            ("light.glsl", "#include \"common.glsl\"\nfloat light;\n"),
+            ("common.glsl", "float pi;\n"),
+        ]);
+        let mut names = Vec::new();
+        let out = preprocess("main.frag", &loaded, &mut names, &mut Vec::new()).unwrap();
+
+        assert_eq!(names, vec!["main.frag", "light.glsl", "common.glsl"]);
+        assert_eq!(out, "#version 300 es\n\
+            #line 1 1\n\
+            #line 1 2\nfloat pi;\n#line 2 1\n\
+            float light;\n\
+            #line 3 0\n\
+            void main() {}\n");
+    }
+
+    #[test]
+    fn line_after_an_include_keeps_its_source_line_number() {
+        let loaded = files(&[
+            ("a.vert", "line 1\n#include \"b.glsl\"\nline 3\nline 4\n"),
+            ("b.glsl", "b 1\nb 2\n"),
+        ]);
+        let out = preprocess("a.vert", &loaded, &mut Vec::new(), &mut Vec::new()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        // a compile error on "line 3" has to be reported as line 3 of source string 0
+        let directive = lines.iter().position(|line| *line == "#line 3 0").unwrap();
+        assert_eq!(lines[directive + 1], "line 3");
+        assert_eq!(lines[directive + 2], "line 4");
+        assert_eq!(lines[lines.iter().position(|line| *line == "#line 1 1").unwrap() + 1], "b 1");
+    }
+
+    #[test]
+    fn reports_include_cycles() {
+        let loaded = files(&[
+            ("main.vert", "#include \"a.glsl\"\n"),
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "#include \"a.glsl\"\n"),
+        ]);
+        let error = preprocess("main.vert", &loaded, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert_eq!(error, "include cycle: main.vert -> a.glsl -> b.glsl -> a.glsl");
+
+        let error = preprocess("missing.vert", &loaded, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert_eq!(error, "missing shader file missing.vert");
+    }
+}
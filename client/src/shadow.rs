@@ -0,0 +1,85 @@
+use nalgebra_glm as glm;
+use wasm_bindgen::JsValue;
+use web_sys::*;
+
+use crate::shaders::*;
+use crate::webgl::*;
+
+pub const SHADOW_SHADER: ShaderFiles = ShaderFiles { vert: "shadow.vert.glsl", frag: "shadow.frag.glsl" };
+
+pub const SHADOW_MAP_SIZE: i32 = 2048;
+
+pub struct ShadowMap {
+    pub program: ShaderProgram,
+    pub framebuffer: WebGlFramebuffer,
+    pub depth_texture: Option<WebGlTexture>,
+}
+
+impl ShadowMap {
+    pub async fn load(ctx: &WebGl2RenderingContext) -> Result<ShadowMap, JsValue> {
+        let program = load_shader_program(ctx, &SHADOW_SHADER).await?;
+        let (framebuffer, depth_texture) = make_depth_framebuffer(ctx, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE)
+            .ok_or_else(|| String::from("Unable to create shadow framebuffer"))?;
+
+        Ok(ShadowMap {
+            program,
+            framebuffer,
+            depth_texture,
+        })
+    }
+
+    // Renders the depth of a single mesh as seen from the sun. The caller restores the program and viewport.
+    // Renders each (vao, vertex count, light mvp) into the depth map.
+    pub fn render(&self, ctx: &WebGl2RenderingContext, meshes: &[(&WebGlVertexArrayObject, i32, glm::Mat4)]) {
+        ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.framebuffer));
+        ctx.viewport(0, 0, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE);
+        ctx.clear(WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+
+        self.program.use_program(ctx);
+        for (vao, vertex_count, light_mvp) in meshes {
+            ctx.bind_vertex_array(Some(vao));
+            self.program.set_mat4(ctx, "light_mvp", light_mvp);
+            ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, *vertex_count);
+        }
+
+        ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+    }
+}
+
+// Builds an orthographic sun camera that tightly encloses the mesh bounding box after it is placed by `model`.
+pub fn fit_light_view_projection(sun_direction: &glm::Vec3, bounds: &(glm::Vec3, glm::Vec3), model: &glm::Mat4) -> glm::Mat4 {
+    let (min, max) = bounds;
+    let corners: Vec<glm::Vec3> = (0..8).map(|i| {
+        let corner = glm::vec4(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+            1.0
+        );
+        (model * corner).xyz()
+    }).collect();
+
+    let center = corners.iter().fold(glm::Vec3::zeros(), |acc, c| acc + c) / 8.0;
+    let radius = corners.iter().map(|c| glm::distance(c, &center)).fold(0.0, f32::max);
+
+    let eye = center + sun_direction * radius * 2.0;
+    let view = glm::look_at(&eye, &center, &glm::vec3(0.0, 0.0, 1.0));
+
+    let mut lo = glm::vec3(f32::MAX, f32::MAX, f32::MAX);
+    let mut hi = glm::vec3(f32::MIN, f32::MIN, f32::MIN);
+    for corner in &corners {
+        let v = (view * glm::vec4(corner.x, corner.y, corner.z, 1.0)).xyz();
+        lo = glm::min2(&lo, &v);
+        hi = glm::max2(&hi, &v);
+    }
+
+    // keep the silhouette off the border texels so the PCF taps never wrap into it
+    let pad = (hi - lo) * 0.05;
+    let proj = glm::ortho(
+        lo.x - pad.x, hi.x + pad.x,
+        lo.y - pad.y, hi.y + pad.y,
+        -hi.z - pad.z, -lo.z + pad.z
+    );
+
+    proj * view
+}
@@ -0,0 +1,83 @@
+use nalgebra_glm as glm;
+use wasm_bindgen::JsValue;
+use web_sys::*;
+
+use crate::load_model::*;
+use crate::shaders::*;
+use crate::webgl::*;
+
+pub const SKY_SHADER: ShaderFiles = ShaderFiles { vert: "sky.vert.glsl", frag: "sky.frag.glsl" };
+
+const GRADIENT_ELEVATIONS: usize = 64;
+const GRADIENT_ALTITUDES: usize = 32;
+// Altitude covered by the top row of the gradient, past this the sky stays black.
+const GRADIENT_MAX_ALTITUDE_METERS: f32 = 60_000.0;
+const SCALE_HEIGHT_METERS: f32 = 8_500.0;
+
+pub struct Sky {
+    pub program: ShaderProgram,
+    pub vao: (WebGlVertexArrayObject, i32),
+    pub gradient: Option<WebGlTexture>,
+}
+
+impl Sky {
+    pub async fn load(ctx: &WebGl2RenderingContext) -> Result<Sky, JsValue> {
+        let program = load_shader_program(ctx, &SKY_SHADER).await?;
+        let (verts, _, _) = make_plane();
+        let vao = make_vao_vert(ctx, &program, &verts)?;
+        let gradient = make_texture(ctx, sky_gradient().as_slice(), GRADIENT_ELEVATIONS as i32, GRADIENT_ALTITUDES as i32);
+
+        Ok(Sky {
+            program,
+            vao,
+            gradient,
+        })
+    }
+
+    // Fills the whole viewport with the sky as seen from `altitude` metres, without touching depth.
+    pub fn render(&self, ctx: &WebGl2RenderingContext, proj: &glm::Mat4, view: &glm::Mat4, sun_direction: &glm::Vec3, altitude: f32) {
+        // only the rotation of the camera matters for a sky at infinity
+        let mut rotation = *view;
+        rotation.set_column(3, &glm::vec4(0.0, 0.0, 0.0, 1.0));
+        let inverse_view_proj = glm::inverse(&(proj * rotation));
+
+        self.program.use_program(ctx);
+        ctx.disable(WebGl2RenderingContext::DEPTH_TEST);
+        self.program.set_texture(ctx, "gradient", self.gradient.as_ref(), 0);
+        self.program.set_mat4(ctx, "inverse_view_proj", &inverse_view_proj);
+        self.program.set_vec3(ctx, "light", sun_direction);
+        self.program.set_f32(ctx, "altitude", (altitude / GRADIENT_MAX_ALTITUDE_METERS).clamp(0.0, 1.0));
+        ctx.bind_vertex_array(Some(&self.vao.0));
+        ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, self.vao.1);
+        ctx.enable(WebGl2RenderingContext::DEPTH_TEST);
+    }
+}
+
+// RGB lookup of sky colour by view elevation (columns) and observer altitude (rows). The air above the
+// observer thins out exponentially, so the zenith fades to black first and the horizon glow last.
+fn sky_gradient() -> Vec<u8> {
+    let space = glm::vec3(0.0, 0.0, 0.02);
+    let zenith = glm::vec3(0.18, 0.42, 0.85);
+    let horizon = glm::vec3(0.72, 0.83, 0.95);
+
+    let mut data = Vec::with_capacity(GRADIENT_ELEVATIONS * GRADIENT_ALTITUDES * 3);
+    for row in 0..GRADIENT_ALTITUDES {
+        let altitude = row as f32 / (GRADIENT_ALTITUDES - 1) as f32 * GRADIENT_MAX_ALTITUDE_METERS;
+        let density = (-altitude / SCALE_HEIGHT_METERS).exp();
+
+        for column in 0..GRADIENT_ELEVATIONS {
+            let elevation = column as f32 / (GRADIENT_ELEVATIONS - 1) as f32 * 1.2 - 0.2;
+            let to_horizon = (1.0 - elevation.max(0.0)).powi(4);
+            let air = glm::lerp(&zenith, &horizon, to_horizon);
+            // looking through more air near the horizon keeps it lit longer
+            let thickness = density.powf(1.0 - 0.6 * to_horizon);
+            let color = glm::lerp(&space, &air, thickness);
+
+            data.push((color.x * 255.0) as u8);
+            data.push((color.y * 255.0) as u8);
+            data.push((color.z * 255.0) as u8);
+        }
+    }
+
+    data
+}
@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+
+use nalgebra_glm as glm;
+
+use crate::earth::EARTH_RADIUS_METERS;
+use crate::load_model::Mesh;
+
+// Terrain meshes are thinned to at most this many samples along a side.
+const MAX_MESH_SAMPLES: usize = 256;
+
+// TIFF tags read from a GeoTIFF.
+const TAG_WIDTH: u16 = 256;
+const TAG_HEIGHT: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_TILE_WIDTH: u16 = 322;
+const TAG_SAMPLE_FORMAT: u16 = 339;
+const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+const TAG_MODEL_TIEPOINT: u16 = 33922;
+const TAG_GEO_KEY_DIRECTORY: u16 = 34735;
+const TAG_GDAL_NODATA: u16 = 42113;
+const GEO_KEY_MODEL_TYPE: u16 = 1024;
+const GEO_KEY_RASTER_TYPE: u16 = 1025;
+const MODEL_TYPE_GEOGRAPHIC: f64 = 2.0;
+const RASTER_PIXEL_IS_POINT: f64 = 2.0;
+
+// Terrain heights on a regular grid, row 0 along the northern edge as in the image files they come from.
+pub struct Heightmap {
+    pub width: usize,
+    pub height: usize,
+    pub heights: Vec<f32>, // m
+    // scene (east, north) of the first sample, and the step to the next column and row
+    pub origin: (f64, f64),
+    pub spacing: (f64, f64),
+}
+
+impl Heightmap {
+    fn at(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.width + column]
+    }
+
+    // Bilinear height at a scene position, None off the edge of the map.
+    pub fn sample(&self, east: f64, north: f64) -> Option<f32> {
+        let x = (east - self.origin.0) / self.spacing.0;
+        let y = (north - self.origin.1) / self.spacing.1;
+        if x < 0.0 || y < 0.0 || x > (self.width - 1) as f64 || y > (self.height - 1) as f64 {
+            return None;
+        }
+        let (column, row) = ((x as usize).min(self.width - 2), (y as usize).min(self.height - 2));
+        let (fx, fy) = ((x - column as f64) as f32, (y - row as f64) as f32);
+        let top = glm::lerp_scalar(self.at(column, row), self.at(column + 1, row), fx);
+        let bottom = glm::lerp_scalar(self.at(column, row + 1), self.at(column + 1, row + 1), fx);
+        Some(glm::lerp_scalar(top, bottom, fy))
+    }
+
+    pub fn shift(&mut self, meters: f32) {
+        for height in &mut self.heights {
+            *height += meters;
+        }
+    }
+}
+
+// East and north metres from the origin, flat earth, which is plenty over a launch site.
+pub fn enu_offset(origin_latitude: f64, origin_longitude: f64, latitude: f64, longitude: f64) -> (f64, f64) {
+    let meters_per_degree = EARTH_RADIUS_METERS * std::f64::consts::PI / 180.0;
+    ((longitude - origin_longitude) * meters_per_degree * origin_latitude.to_radians().cos(),
+     (latitude - origin_latitude) * meters_per_degree)
+}
+
+// A grey heightmap image carries no position, so it is centred on the site and stretched to `size_meters`
+// square, black at `min_height` and white at `max_height`. Colour images use their first channel.
+pub fn parse_heightmap_png(bytes: &[u8], size_meters: f64, min_height: f64, max_height: f64) -> Result<Heightmap, String> {
+    // the config leaves these at 0 when they are missing, which would collapse the map onto the site
+    if !(size_meters > 0.0 && size_meters.is_finite()) {
+        return Err(format!("heightmap needs a positive size_meters, not {}", size_meters));
+    }
+    if !(max_height >= min_height && min_height.is_finite() && max_height.is_finite()) {
+        return Err(format!("heightmap max_height {} is below min_height {}", max_height, min_height));
+    }
+    // the decoder strips 16 bit samples to 8 by default, keep them for the finer height steps
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut data = vec![0u8; info.buffer_size()];
+    reader.next_frame(&mut data).map_err(|e| e.to_string())?;
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    if width < 2 || height < 2 {
+        return Err(format!("heightmap is only {}x{}", width, height));
+    }
+    let wide = info.bit_depth == png::BitDepth::Sixteen;
+    let pixel_bytes = info.color_type.samples() * if wide { 2 } else { 1 };
+    let full_scale = if wide { 65535.0 } else { 255.0 };
+
+    let heights = data.chunks(info.line_size).flat_map(|line| {
+        line.chunks(pixel_bytes).take(width).map(|pixel| {
+            let value = if wide { u16::from_be_bytes([pixel[0], pixel[1]]) as f64 } else { pixel[0] as f64 };
+            (min_height + (max_height - min_height) * value / full_scale) as f32
+        })
+    }).collect();
+
+    let spacing = (size_meters / (width - 1) as f64, -size_meters / (height - 1) as f64);
+    Ok(Heightmap {
+        width,
+        height,
+        heights,
+        origin: (-size_meters / 2.0, size_meters / 2.0),
+        spacing,
+    })
+}
+
+struct TiffEntry {
+    kind: u16,
+    count: usize,
+    // where the values are, inline in the entry when they fit in 4 bytes
+    at: usize,
+}
+
+struct Tiff<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+    entries: HashMap<u16, TiffEntry>,
+}
+
+impl<'a> Tiff<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Tiff<'a>, String> {
+        let little_endian = match bytes.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err("not a tiff file".to_string()),
+        };
+        let mut tiff = Tiff { bytes, little_endian, entries: HashMap::new() };
+        match tiff.u16(2)? {
+            42 => {}
+            43 => return Err("BigTIFF files are not supported".to_string()),
+            _ => return Err("not a tiff file".to_string()),
+        }
+
+        // only the first image directory, later ones hold overviews
+        let directory = tiff.u32(4)? as usize;
+        for i in 0..tiff.u16(directory)? as usize {
+            let entry = directory + 2 + i * 12;
+            let kind = tiff.u16(entry + 2)?;
+            let count = tiff.u32(entry + 4)? as usize;
+            let size = type_size(kind) * count;
+            let at = if size <= 4 { entry + 8 } else { tiff.u32(entry + 8)? as usize };
+            tiff.entries.insert(tiff.u16(entry)?, TiffEntry { kind, count, at });
+        }
+        Ok(tiff)
+    }
+
+    fn bytes<const N: usize>(&self, at: usize) -> Result<[u8; N], String> {
+        let mut field = [0; N];
+        field.copy_from_slice(self.bytes.get(at..at + N).ok_or("tiff file is truncated")?);
+        if !self.little_endian {
+            field.reverse();
+        }
+        Ok(field)
+    }
+
+    fn u16(&self, at: usize) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(at)?))
+    }
+
+    fn u32(&self, at: usize) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(at)?))
+    }
+
+    // One number of field type `kind` at `at`, also used for pixel samples.
+    fn number(&self, kind: u16, at: usize) -> Result<f64, String> {
+        Ok(match kind {
+            1 => self.bytes::<1>(at)?[0] as f64,
+            6 => self.bytes::<1>(at)?[0] as i8 as f64,
+            3 => self.u16(at)? as f64,
+            8 => self.u16(at)? as i16 as f64,
+            4 => self.u32(at)? as f64,
+            9 => self.u32(at)? as i32 as f64,
+            11 => f32::from_le_bytes(self.bytes(at)?) as f64,
+            12 => f64::from_le_bytes(self.bytes(at)?),
+            _ => return Err(format!("unsupported tiff field type {}", kind)),
+        })
+    }
+
+    fn values(&self, tag: u16) -> Result<Option<Vec<f64>>, String> {
+        let entry = match self.entries.get(&tag) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let size = type_size(entry.kind);
+        (0..entry.count).map(|i| self.number(entry.kind, entry.at + i * size)).collect::<Result<Vec<_>, _>>().map(Some)
+    }
+
+    fn value(&self, tag: u16) -> Result<Option<f64>, String> {
+        Ok(self.values(tag)?.and_then(|values| values.first().copied()))
+    }
+
+    fn required(&self, tag: u16, name: &str) -> Result<Vec<f64>, String> {
+        self.values(tag)?.ok_or_else(|| format!("tiff has no {}", name))
+    }
+
+    fn text(&self, tag: u16) -> Option<String> {
+        let entry = self.entries.get(&tag).filter(|entry| entry.kind == 2)?;
+        let bytes = self.bytes.get(entry.at..entry.at + entry.count)?;
+        Some(String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string())
+    }
+}
+
+fn type_size(kind: u16) -> usize {
+    match kind {
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        12 => 8,
+        _ => 1,
+    }
+}
+
+// Single band, uncompressed, stripped GeoTIFF in geographic (latitude/longitude) coordinates, placed
+// around the site at the given origin. Nodata samples take the lowest real height.
+pub fn parse_geotiff(bytes: &[u8], origin_latitude: f64, origin_longitude: f64) -> Result<Heightmap, String> {
+    let tiff = Tiff::parse(bytes)?;
+    let width = tiff.value(TAG_WIDTH)?.ok_or("tiff has no width")? as usize;
+    let height = tiff.value(TAG_HEIGHT)?.ok_or("tiff has no height")? as usize;
+    if width < 2 || height < 2 {
+        return Err(format!("DEM is only {}x{}", width, height));
+    }
+    if tiff.value(TAG_COMPRESSION)?.unwrap_or(1.0) != 1.0 {
+        return Err("compressed GeoTIFFs are not supported, re-save without compression".to_string());
+    }
+    if tiff.entries.contains_key(&TAG_TILE_WIDTH) {
+        return Err("tiled GeoTIFFs are not supported, re-save with strips".to_string());
+    }
+    if tiff.value(TAG_SAMPLES_PER_PIXEL)?.unwrap_or(1.0) != 1.0 {
+        return Err("DEM must have a single band".to_string());
+    }
+
+    let bits = tiff.value(TAG_BITS_PER_SAMPLE)?.unwrap_or(1.0) as usize;
+    let sample_kind = match (bits, tiff.value(TAG_SAMPLE_FORMAT)?.unwrap_or(1.0) as u16) {
+        (8, 1) => 1,
+        (8, 2) => 6,
+        (16, 1) => 3,
+        (16, 2) => 8,
+        (32, 1) => 4,
+        (32, 2) => 9,
+        (32, 3) => 11,
+        (64, 3) => 12,
+        (bits, format) => return Err(format!("unsupported sample type: {} bits, format {}", bits, format)),
+    };
+
+    let offsets = tiff.required(TAG_STRIP_OFFSETS, "strip offsets")?;
+    let counts = tiff.required(TAG_STRIP_BYTE_COUNTS, "strip byte counts")?;
+    let mut raster = Vec::with_capacity(width * height * bits / 8);
+    for (offset, count) in offsets.iter().zip(counts.iter()) {
+        let (offset, count) = (*offset as usize, *count as usize);
+        raster.extend_from_slice(bytes.get(offset..offset + count).ok_or("tiff strip runs past the end of the file")?);
+    }
+    let sample_bytes = bits / 8;
+    if raster.len() < width * height * sample_bytes {
+        return Err("tiff strips hold fewer samples than the image size".to_string());
+    }
+    let samples = Tiff { bytes: &raster, little_endian: tiff.little_endian, entries: HashMap::new() };
+    let nodata = tiff.text(TAG_GDAL_NODATA).and_then(|text| text.parse::<f64>().ok());
+    let mut heights = (0..width * height)
+        .map(|i| samples.number(sample_kind, i * sample_bytes).map(|h| if Some(h) == nodata || !h.is_finite() { f32::NAN } else { h as f32 }))
+        .collect::<Result<Vec<_>, _>>()?;
+    let lowest = heights.iter().copied().filter(|h| !h.is_nan()).fold(f32::INFINITY, f32::min);
+    if !lowest.is_finite() {
+        return Err("DEM has no valid heights".to_string());
+    }
+    for h in &mut heights {
+        if h.is_nan() {
+            *h = lowest;
+        }
+    }
+
+    // geo keys are a header of 4 shorts, then (key, location, count, value) per key
+    let keys = tiff.values(TAG_GEO_KEY_DIRECTORY)?.unwrap_or_default();
+    let geo_key = |key: u16| keys.chunks(4).skip(1).find(|k| k.len() == 4 && k[0] as u16 == key && k[1] == 0.0).map(|k| k[3]);
+    if geo_key(GEO_KEY_MODEL_TYPE).is_some_and(|model| model != MODEL_TYPE_GEOGRAPHIC) {
+        return Err("DEM must be in latitude/longitude, reproject it to EPSG:4326".to_string());
+    }
+    let scale = tiff.required(TAG_MODEL_PIXEL_SCALE, "pixel scale")?;
+    let tiepoint = tiff.required(TAG_MODEL_TIEPOINT, "tiepoint")?;
+    if scale.len() < 2 || tiepoint.len() < 6 {
+        return Err("malformed GeoTIFF georeferencing".to_string());
+    }
+    // tie points name the corner of a pixel unless the raster says samples are points
+    let centre = if geo_key(GEO_KEY_RASTER_TYPE) == Some(RASTER_PIXEL_IS_POINT) { 0.0 } else { 0.5 };
+    let first_longitude = tiepoint[3] + (centre - tiepoint[0]) * scale[0];
+    let first_latitude = tiepoint[4] - (centre - tiepoint[1]) * scale[1];
+    if first_latitude.abs() > 90.0 || first_longitude.abs() > 360.0 {
+        return Err("DEM coordinates are not latitude/longitude".to_string());
+    }
+
+    let origin = enu_offset(origin_latitude, origin_longitude, first_latitude, first_longitude);
+    let (east_step, _) = enu_offset(origin_latitude, origin_longitude, origin_latitude, origin_longitude + scale[0]);
+    let (_, north_step) = enu_offset(origin_latitude, origin_longitude, origin_latitude - scale[1], origin_longitude);
+    Ok(Heightmap { width, height, heights, origin, spacing: (east_step, north_step) })
+}
+
+// Lit triangle mesh of the map in scene space, every vertex using `uv`.
+pub fn terrain_mesh(map: &Heightmap, uv: [f32; 2]) -> Mesh {
+    let step = map.width.max(map.height).div_ceil(MAX_MESH_SAMPLES).max(1);
+    let columns: Vec<usize> = (0..map.width).step_by(step).collect();
+    let rows: Vec<usize> = (0..map.height).step_by(step).collect();
+
+    let point = |column: usize, row: usize| glm::vec3(
+        (map.origin.0 + column as f64 * map.spacing.0) as f32,
+        (map.origin.1 + row as f64 * map.spacing.1) as f32,
+        map.at(column, row),
+    );
+    let normal = |column: usize, row: usize| {
+        let (left, right) = (column.saturating_sub(1), (column + 1).min(map.width - 1));
+        let (up, down) = (row.saturating_sub(1), (row + 1).min(map.height - 1));
+        let east = point(right, row) - point(left, row);
+        let south = point(column, down) - point(column, up);
+        glm::normalize(&glm::cross(&south, &east))
+    };
+
+    let mut mesh: Mesh = (Vec::new(), Vec::new(), Vec::new());
+    for r in rows.windows(2) {
+        for c in columns.windows(2) {
+            // rows run south, so this winding is counter clockwise seen from above
+            let quad = [(c[0], r[0]), (c[0], r[1]), (c[1], r[0]), (c[1], r[0]), (c[0], r[1]), (c[1], r[1])];
+            for (column, row) in quad.iter() {
+                let (p, n) = (point(*column, *row), normal(*column, *row));
+                mesh.0.extend_from_slice(&[p.x, p.y, p.z]);
+                mesh.1.extend_from_slice(&[n.x, n.y, n.z]);
+                mesh.2.extend_from_slice(&uv);
+            }
+        }
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SITE: (f64, f64) = (41.5, -89.5);
+    const PIXEL: f64 = 0.001; // degrees
+
+    fn encode(out: &mut Vec<u8>, little_endian: bool, kind: u16, value: f64) {
+        let mut bytes = match kind {
+            1 | 2 => vec![value as u8],
+            3 => (value as u16).to_le_bytes().to_vec(),
+            8 => (value as i16).to_le_bytes().to_vec(),
+            4 => (value as u32).to_le_bytes().to_vec(),
+            11 => (value as f32).to_le_bytes().to_vec(),
+            12 => value.to_le_bytes().to_vec(),
+            _ => panic!("no encoding for field type {}", kind),
+        };
+        if !little_endian {
+            bytes.reverse();
+        }
+        out.extend_from_slice(&bytes);
+    }
+
+    // A stripped GeoTIFF with the raster straight after the header and the directory after that, `tags`
+    // as (tag, field type, values).
+    fn geotiff(little_endian: bool, width: usize, height: usize, sample: (u16, u16), heights: &[f64], tags: &[(u16, u16, Vec<f64>)]) -> Vec<u8> {
+        let mut raster = Vec::new();
+        for h in heights {
+            encode(&mut raster, little_endian, sample.0, *h);
+        }
+        let mut tags: Vec<(u16, u16, Vec<f64>)> = tags.to_vec();
+        tags.extend(vec![
+            (TAG_WIDTH, 3, vec![width as f64]),
+            (TAG_HEIGHT, 3, vec![height as f64]),
+            (TAG_BITS_PER_SAMPLE, 3, vec![(type_size(sample.0) * 8) as f64]),
+            (TAG_SAMPLE_FORMAT, 3, vec![sample.1 as f64]),
+            (TAG_STRIP_OFFSETS, 4, vec![8.0]),
+            (TAG_STRIP_BYTE_COUNTS, 4, vec![raster.len() as f64]),
+        ]);
+
+        let mut out = if little_endian { b"II".to_vec() } else { b"MM".to_vec() };
+        encode(&mut out, little_endian, 3, 42.0);
+        encode(&mut out, little_endian, 4, (8 + raster.len()) as f64);
+        out.extend_from_slice(&raster);
+        let mut data_at = out.len() + 2 + tags.len() * 12 + 4;
+        let mut data = Vec::new();
+        encode(&mut out, little_endian, 3, tags.len() as f64);
+        for (tag, kind, values) in &tags {
+            let mut bytes = Vec::new();
+            for value in values {
+                encode(&mut bytes, little_endian, *kind, *value);
+            }
+            encode(&mut out, little_endian, 3, *tag as f64);
+            encode(&mut out, little_endian, 3, *kind as f64);
+            encode(&mut out, little_endian, 4, values.len() as f64);
+            if bytes.len() <= 4 {
+                bytes.resize(4, 0);
+                out.extend_from_slice(&bytes);
+            } else {
+                encode(&mut out, little_endian, 4, data_at as f64);
+                data_at += bytes.len();
+                data.extend_from_slice(&bytes);
+            }
+        }
+        encode(&mut out, little_endian, 4, 0.0);
+        out.extend_from_slice(&data);
+        out
+    }
+
+    // Georeferencing with the corner of pixel `raster` at `corner`.
+    fn placed(raster: (f64, f64), corner: (f64, f64)) -> Vec<(u16, u16, Vec<f64>)> {
+        vec![
+            (TAG_MODEL_PIXEL_SCALE, 12, vec![PIXEL, PIXEL, 0.0]),
+            (TAG_MODEL_TIEPOINT, 12, vec![raster.0, raster.1, 0.0, corner.1, corner.0, 0.0]),
+        ]
+    }
+
+    fn geo_keys(keys: &[(u16, f64)]) -> (u16, u16, Vec<f64>) {
+        let mut values = vec![1.0, 1.0, 0.0, keys.len() as f64];
+        for (key, value) in keys {
+            values.extend_from_slice(&[*key as f64, 0.0, 1.0, *value]);
+        }
+        (TAG_GEO_KEY_DIRECTORY, 3, values)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn reads_both_byte_orders() {
+        let heights: [f64; 6] = [100.0, -5.0, 300.0, 1200.0, 0.0, 7.0];
+        for sample in [(3, 1), (8, 2), (4, 1), (11, 3), (12, 3)].iter() {
+            let heights: Vec<f64> = heights.iter().map(|h| if sample.1 == 1 { h.abs() } else { *h }).collect();
+            for little_endian in [true, false].iter() {
+                let bytes = geotiff(*little_endian, 3, 2, *sample, &heights, &placed((0.0, 0.0), SITE));
+                let map = parse_geotiff(&bytes, SITE.0, SITE.1).unwrap();
+                assert_eq!((map.width, map.height), (3, 2));
+                let read: Vec<f64> = map.heights.iter().map(|h| *h as f64).collect();
+                assert_eq!(read, heights, "sample type {:?}, little endian {}", sample, little_endian);
+            }
+        }
+    }
+
+    #[test]
+    fn places_pixel_centres_from_the_tiepoint_and_scale() {
+        let heights = [1.0; 6];
+        let (east_step, north_step) = enu_offset(SITE.0, SITE.1, SITE.0 - PIXEL, SITE.1 + PIXEL);
+
+        // tie point on the corner of the first pixel, its centre is half a pixel in
+        let map = parse_geotiff(&geotiff(true, 3, 2, (3, 1), &heights, &placed((0.0, 0.0), SITE)), SITE.0, SITE.1).unwrap();
+        assert!(close(map.spacing.0, east_step) && close(map.spacing.1, north_step));
+        assert!(map.spacing.1 < 0.0);
+        assert!(close(map.origin.0, east_step / 2.0) && close(map.origin.1, north_step / 2.0));
+
+        // tie point on pixel (1, 1) instead of the first
+        let map = parse_geotiff(&geotiff(true, 3, 2, (3, 1), &heights, &placed((1.0, 1.0), SITE)), SITE.0, SITE.1).unwrap();
+        assert!(close(map.origin.0, -east_step / 2.0) && close(map.origin.1, -north_step / 2.0));
+
+        // pixel is point, the tie point is the first sample itself
+        let mut tags = placed((0.0, 0.0), SITE);
+        tags.push(geo_keys(&[(GEO_KEY_MODEL_TYPE, MODEL_TYPE_GEOGRAPHIC), (GEO_KEY_RASTER_TYPE, RASTER_PIXEL_IS_POINT)]));
+        let map = parse_geotiff(&geotiff(false, 3, 2, (3, 1), &heights, &tags), SITE.0, SITE.1).unwrap();
+        assert!(close(map.origin.0, 0.0) && close(map.origin.1, 0.0));
+        assert_eq!(map.sample(0.0, 0.0), Some(1.0));
+    }
+
+    #[test]
+    fn fills_nodata_with_the_lowest_height() {
+        let mut tags = placed((0.0, 0.0), SITE);
+        tags.push((TAG_GDAL_NODATA, 2, b"-9999\0".iter().map(|b| *b as f64).collect()));
+        let bytes = geotiff(true, 2, 2, (11, 3), &[-9999.0, 250.0, 240.0, f64::NAN], &tags);
+        assert_eq!(parse_geotiff(&bytes, SITE.0, SITE.1).unwrap().heights, vec![240.0, 250.0, 240.0, 240.0]);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_read() {
+        let heights = [1.0; 4];
+        let error = |bytes: Vec<u8>| parse_geotiff(&bytes, SITE.0, SITE.1).err().unwrap();
+
+        let mut tags = placed((0.0, 0.0), SITE);
+        tags.push((TAG_COMPRESSION, 3, vec![5.0]));
+        assert!(error(geotiff(true, 2, 2, (3, 1), &heights, &tags)).contains("compressed"));
+
+        let mut tags = placed((0.0, 0.0), SITE);
+        tags.push(geo_keys(&[(GEO_KEY_MODEL_TYPE, 1.0)]));
+        assert!(error(geotiff(true, 2, 2, (3, 1), &heights, &tags)).contains("latitude/longitude"));
+
+        assert!(error(geotiff(true, 2, 2, (3, 3), &heights, &placed((0.0, 0.0), SITE))).contains("unsupported sample type"));
+        assert!(error(geotiff(true, 2, 2, (3, 1), &heights, &[])).contains("pixel scale"));
+        assert!(error(b"MM\0\x2b".to_vec()).contains("BigTIFF"));
+        let mut truncated = geotiff(true, 2, 2, (3, 1), &heights, &placed((0.0, 0.0), SITE));
+        truncated.truncate(20);
+        assert!(error(truncated).contains("truncated"));
+    }
+
+    fn png(width: u32, height: u32, depth: png::BitDepth, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(depth);
+        encoder.write_header().unwrap().write_image_data(data).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn scales_heightmap_pngs() {
+        let map = parse_heightmap_png(&png(3, 2, png::BitDepth::Eight, &[0, 51, 255, 255, 102, 0]), 200.0, 100.0, 200.0).unwrap();
+        assert_eq!(map.heights, vec![100.0, 120.0, 200.0, 200.0, 140.0, 100.0]);
+        assert_eq!((map.origin, map.spacing), ((-100.0, 100.0), (100.0, -200.0)));
+        // centre of the top edge, then halfway down the middle column
+        assert_eq!(map.sample(0.0, 100.0), Some(120.0));
+        assert_eq!(map.sample(0.0, 0.0), Some(130.0));
+        assert_eq!(map.sample(101.0, 0.0), None);
+
+        // sixteen bit samples are big endian
+        let map = parse_heightmap_png(&png(2, 2, png::BitDepth::Sixteen, &[0xff, 0xff, 0x80, 0x00, 0x00, 0x01, 0, 0]), 10.0, 0.0, 65535.0).unwrap();
+        assert_eq!(map.heights, vec![65535.0, 32768.0, 1.0, 0.0]);
+
+        assert!(parse_heightmap_png(&png(1, 2, png::BitDepth::Eight, &[0, 0]), 10.0, 0.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn rejects_heightmaps_without_a_size_or_height_range() {
+        let image = png(2, 2, png::BitDepth::Eight, &[0, 255, 255, 0]);
+        // {"terrain": {"path": "dem.png"}} leaves all three at 0
+        assert!(parse_heightmap_png(&image, 0.0, 0.0, 0.0).err().unwrap().contains("size_meters"));
+        assert!(parse_heightmap_png(&image, -50.0, 0.0, 10.0).err().unwrap().contains("size_meters"));
+        assert!(parse_heightmap_png(&image, 100.0, 20.0, 10.0).err().unwrap().contains("below min_height"));
+        // a flat map is fine
+        let map = parse_heightmap_png(&image, 100.0, 10.0, 10.0).unwrap();
+        assert_eq!(map.sample(0.0, 0.0), Some(10.0));
+    }
+}
@@ -0,0 +1,313 @@
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Quantity {
+    Time,
+    Length,
+    Speed,
+    Acceleration,
+    AngularRate,
+    MagneticField,
+    Angle,
+    Temperature,
+    Pressure,
+    Dimensionless,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Unit {
+    Millisecond,
+    Second,
+    Meter,
+    Foot,
+    MeterPerSecond,
+    FootPerSecond,
+    KilometerPerHour,
+    MilePerHour,
+    StandardGravity,
+    MeterPerSecondSquared,
+    FootPerSecondSquared,
+    DegreePerSecond,
+    RadianPerSecond,
+    Gauss,
+    Degree,
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+    Pascal,
+    Hectopascal,
+    Kilopascal,
+    Psi,
+    None,
+}
+
+const UNITS: [Unit; 23] = [
+    Unit::Millisecond, Unit::Second,
+    Unit::Meter, Unit::Foot,
+    Unit::MeterPerSecond, Unit::FootPerSecond, Unit::KilometerPerHour, Unit::MilePerHour,
+    Unit::StandardGravity, Unit::MeterPerSecondSquared, Unit::FootPerSecondSquared,
+    Unit::DegreePerSecond, Unit::RadianPerSecond,
+    Unit::Gauss,
+    Unit::Degree,
+    Unit::Celsius, Unit::Fahrenheit, Unit::Kelvin,
+    Unit::Pascal, Unit::Hectopascal, Unit::Kilopascal, Unit::Psi,
+    Unit::None,
+];
+
+const FOOT: f64 = 0.3048;
+const STANDARD_GRAVITY: f64 = 9.80665;
+
+impl Unit {
+    pub fn quantity(&self) -> Quantity {
+        match self {
+            Unit::Millisecond | Unit::Second => Quantity::Time,
+            Unit::Meter | Unit::Foot => Quantity::Length,
+            Unit::MeterPerSecond | Unit::FootPerSecond | Unit::KilometerPerHour | Unit::MilePerHour => Quantity::Speed,
+            Unit::StandardGravity | Unit::MeterPerSecondSquared | Unit::FootPerSecondSquared => Quantity::Acceleration,
+            Unit::DegreePerSecond | Unit::RadianPerSecond => Quantity::AngularRate,
+            Unit::Gauss => Quantity::MagneticField,
+            Unit::Degree => Quantity::Angle,
+            Unit::Celsius | Unit::Fahrenheit | Unit::Kelvin => Quantity::Temperature,
+            Unit::Pascal | Unit::Hectopascal | Unit::Kilopascal | Unit::Psi => Quantity::Pressure,
+            Unit::None => Quantity::Dimensionless,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Millisecond => "ms",
+            Unit::Second => "s",
+            Unit::Meter => "m",
+            Unit::Foot => "ft",
+            Unit::MeterPerSecond => "m/s",
+            Unit::FootPerSecond => "ft/s",
+            Unit::KilometerPerHour => "km/h",
+            Unit::MilePerHour => "mph",
+            Unit::StandardGravity => "g",
+            Unit::MeterPerSecondSquared => "m/s\u{b2}",
+            Unit::FootPerSecondSquared => "ft/s\u{b2}",
+            Unit::DegreePerSecond => "deg/s",
+            Unit::RadianPerSecond => "rad/s",
+            Unit::Gauss => "G",
+            Unit::Degree => "deg",
+            Unit::Celsius => "\u{b0}C",
+            Unit::Fahrenheit => "\u{b0}F",
+            Unit::Kelvin => "K",
+            Unit::Pascal => "Pa",
+            Unit::Hectopascal => "hPa",
+            Unit::Kilopascal => "kPa",
+            Unit::Psi => "psi",
+            Unit::None => "",
+        }
+    }
+
+    // Accepts the symbols above, with ^2 or 2 for the squared sign and C/F without the degree sign.
+    pub fn from_symbol(symbol: &str) -> Option<Unit> {
+        let symbol = symbol.trim().replace("^2", "\u{b2}").replace("s2", "s\u{b2}").replace('\u{b0}', "");
+        let bare = |unit: &Unit| unit.symbol().replace('\u{b0}', "");
+        // exact case first, "G" (gauss) and "g" are different units
+        UNITS.iter().copied().find(|unit| bare(unit) == symbol)
+            .or_else(|| UNITS.iter().copied().find(|unit| bare(unit).eq_ignore_ascii_case(&symbol)))
+    }
+
+    // (scale, offset) taking a value in this unit to the SI unit of its quantity, si = value * scale + offset.
+    fn si_scale(self) -> (f64, f64) {
+        match self {
+            Unit::Millisecond => (0.001, 0.0),
+            Unit::Foot | Unit::FootPerSecond | Unit::FootPerSecondSquared => (FOOT, 0.0),
+            Unit::KilometerPerHour => (1.0 / 3.6, 0.0),
+            Unit::MilePerHour => (1609.344 / 3600.0, 0.0),
+            Unit::StandardGravity => (STANDARD_GRAVITY, 0.0),
+            Unit::DegreePerSecond | Unit::Degree => (PI / 180.0, 0.0),
+            Unit::Celsius => (1.0, 273.15),
+            Unit::Fahrenheit => (5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0),
+            Unit::Hectopascal => (100.0, 0.0),
+            Unit::Kilopascal => (1000.0, 0.0),
+            Unit::Psi => (6894.757293168, 0.0),
+            _ => (1.0, 0.0),
+        }
+    }
+}
+
+// Converts `value` between two units of the same quantity.
+pub fn convert(value: f64, from: Unit, to: Unit) -> f64 {
+    debug_assert_eq!(from.quantity(), to.quantity(), "converting {:?} to {:?}", from, to);
+    if from == to {
+        return value;
+    }
+    let (from_scale, from_offset) = from.si_scale();
+    let (to_scale, to_offset) = to.si_scale();
+    (value * from_scale + from_offset - to_offset) / to_scale
+}
+
+// The unit each quantity is shown in. Quantities without a setting here (time, angles, the magnetometer)
+// are always shown as logged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayUnits {
+    pub length: Unit,
+    pub speed: Unit,
+    pub acceleration: Unit,
+    pub angular_rate: Unit,
+    pub temperature: Unit,
+    pub pressure: Unit,
+}
+
+impl Default for DisplayUnits {
+    fn default() -> Self {
+        DisplayUnits::metric()
+    }
+}
+
+impl DisplayUnits {
+    pub fn metric() -> DisplayUnits {
+        DisplayUnits {
+            length: Unit::Meter,
+            speed: Unit::MeterPerSecond,
+            acceleration: Unit::StandardGravity,
+            angular_rate: Unit::DegreePerSecond,
+            temperature: Unit::Celsius,
+            pressure: Unit::Hectopascal,
+        }
+    }
+
+    pub fn imperial() -> DisplayUnits {
+        DisplayUnits {
+            length: Unit::Foot,
+            speed: Unit::MilePerHour,
+            acceleration: Unit::StandardGravity,
+            angular_rate: Unit::DegreePerSecond,
+            temperature: Unit::Fahrenheit,
+            pressure: Unit::Psi,
+        }
+    }
+
+    // Parses a comma separated list of "metric", "imperial" and single overrides given as a unit symbol,
+    // e.g. "imperial, m/s^2" or "ft, kPa".
+    pub fn parse(text: &str) -> Result<DisplayUnits, String> {
+        let mut units = DisplayUnits::metric();
+        for entry in text.split([',', '\n']).map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.to_lowercase().as_str() {
+                "metric" => units = DisplayUnits::metric(),
+                "imperial" => units = DisplayUnits::imperial(),
+                _ => {
+                    let unit = Unit::from_symbol(entry).ok_or_else(|| format!("unknown unit \"{}\"", entry))?;
+                    *units.setting(unit.quantity()).ok_or_else(|| format!("{} can't be changed", entry))? = unit;
+                }
+            }
+        }
+        Ok(units)
+    }
+
+    fn setting(&mut self, quantity: Quantity) -> Option<&mut Unit> {
+        match quantity {
+            Quantity::Length => Some(&mut self.length),
+            Quantity::Speed => Some(&mut self.speed),
+            Quantity::Acceleration => Some(&mut self.acceleration),
+            Quantity::AngularRate => Some(&mut self.angular_rate),
+            Quantity::Temperature => Some(&mut self.temperature),
+            Quantity::Pressure => Some(&mut self.pressure),
+            _ => None,
+        }
+    }
+
+    // Display unit for a value logged in `raw`.
+    pub fn unit_for(&self, raw: Unit) -> Unit {
+        match raw.quantity() {
+            Quantity::Length => self.length,
+            Quantity::Speed => self.speed,
+            Quantity::Acceleration => self.acceleration,
+            Quantity::AngularRate => self.angular_rate,
+            Quantity::Temperature => self.temperature,
+            Quantity::Pressure => self.pressure,
+            _ => raw,
+        }
+    }
+
+    pub fn value(&self, value: f64, raw: Unit) -> f64 {
+        convert(value, raw, self.unit_for(raw))
+    }
+
+    // `value` in its display unit with `decimals` places and the unit symbol.
+    pub fn format(&self, value: f64, raw: Unit, decimals: usize) -> String {
+        let unit = self.unit_for(raw);
+        let value = convert(value, raw, unit);
+        match unit {
+            Unit::None => format!("{:.*}", decimals, value),
+            Unit::Celsius | Unit::Fahrenheit => format!("{:.*}{}", decimals, value, unit.symbol()),
+            _ => format!("{:.*} {}", decimals, value, unit.symbol()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn converts_lengths_and_speeds() {
+        assert!(close(convert(1000.0, Unit::Meter, Unit::Foot), 3280.839895013123));
+        assert!(close(convert(1.0, Unit::Foot, Unit::Meter), 0.3048));
+        assert!(close(convert(100.0, Unit::MeterPerSecond, Unit::MilePerHour), 223.69362920544023));
+        assert!(close(convert(36.0, Unit::KilometerPerHour, Unit::MeterPerSecond), 10.0));
+        assert!(close(convert(10.0, Unit::FootPerSecond, Unit::MeterPerSecond), 3.048));
+    }
+
+    #[test]
+    fn converts_accelerations_and_rates() {
+        assert!(close(convert(1.0, Unit::StandardGravity, Unit::MeterPerSecondSquared), 9.80665));
+        assert!(close(convert(9.80665, Unit::MeterPerSecondSquared, Unit::FootPerSecondSquared), 32.17404855643044));
+        assert!(close(convert(180.0, Unit::DegreePerSecond, Unit::RadianPerSecond), PI));
+    }
+
+    #[test]
+    fn converts_temperatures_with_offsets() {
+        assert!(close(convert(0.0, Unit::Celsius, Unit::Fahrenheit), 32.0));
+        assert!(close(convert(100.0, Unit::Celsius, Unit::Fahrenheit), 212.0));
+        assert!(close(convert(-40.0, Unit::Fahrenheit, Unit::Celsius), -40.0));
+        assert!(close(convert(21.0, Unit::Celsius, Unit::Kelvin), 294.15));
+    }
+
+    #[test]
+    fn converts_pressures() {
+        assert!(close(convert(1013.25, Unit::Hectopascal, Unit::Psi), 14.695948775513449));
+        assert!(close(convert(1013.25, Unit::Hectopascal, Unit::Kilopascal), 101.325));
+        assert!(close(convert(1.0, Unit::Kilopascal, Unit::Pascal), 1000.0));
+    }
+
+    #[test]
+    fn round_trips_every_unit() {
+        for unit in UNITS.iter() {
+            for other in UNITS.iter().filter(|other| other.quantity() == unit.quantity()) {
+                assert!(close(convert(convert(123.4, *unit, *other), *other, *unit), 123.4), "{:?} <-> {:?}", unit, other);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_display_units() {
+        assert_eq!(DisplayUnits::parse("").unwrap(), DisplayUnits::metric());
+        assert_eq!(DisplayUnits::parse("Imperial").unwrap(), DisplayUnits::imperial());
+
+        let units = DisplayUnits::parse("imperial, m/s^2, kPa").unwrap();
+        assert_eq!(units.length, Unit::Foot);
+        assert_eq!(units.acceleration, Unit::MeterPerSecondSquared);
+        assert_eq!(units.pressure, Unit::Kilopascal);
+        assert_eq!(DisplayUnits::parse("F").unwrap().temperature, Unit::Fahrenheit);
+
+        assert!(DisplayUnits::parse("furlongs").is_err());
+        assert!(DisplayUnits::parse("ms").is_err());
+    }
+
+    #[test]
+    fn formats_in_display_units() {
+        let imperial = DisplayUnits::imperial();
+        assert_eq!(imperial.format(1000.0, Unit::Meter, 0), "3281 ft");
+        assert_eq!(imperial.format(20.0, Unit::Celsius, 1), "68.0\u{b0}F");
+        assert_eq!(imperial.format(3.5, Unit::StandardGravity, 2), "3.50 g");
+        assert_eq!(DisplayUnits::metric().format(0.85, Unit::None, 2), "0.85");
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::rocket_data::{Column, RocketData};
+
+// The accelerometer clips at +-24 g and the gyros at +-2000 deg/s, readings this close to full scale are
+// a lower bound rather than a measurement.
+pub const ACCEL_SATURATION_G: f64 = 23.9;
+pub const GYRO_SATURATION_DPS: f64 = 1990.0;
+// A step between timestamps longer than this many nominal sample periods counts as dropped rows.
+const GAP_PERIODS: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    RepeatedSample,
+    TimestampNotIncreasing,
+    TimestampGap,
+    OutOfRange,
+    PositionLockLost,
+    Saturation,
+}
+
+impl AnomalyKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnomalyKind::RepeatedSample => "repeated sample",
+            AnomalyKind::TimestampNotIncreasing => "timestamp not increasing",
+            AnomalyKind::TimestampGap => "timestamp gap",
+            AnomalyKind::OutOfRange => "out of range",
+            AnomalyKind::PositionLockLost => "position lock lost",
+            AnomalyKind::Saturation => "saturation",
+        }
+    }
+}
+
+// A run of consecutive rows with the same problem. `end_row` is exclusive, `field` is the column or sensor
+// group involved, or empty when the whole row is.
+#[derive(Clone, Debug, Serialize)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub field: &'static str,
+    pub start_row: usize,
+    pub end_row: usize,
+    pub start_timestamp: u32,
+    pub end_timestamp: u32,
+    pub detail: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ValidationReport {
+    pub rows: usize,
+    pub sample_period_ms: u32,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl ValidationReport {
+    pub fn count(&self, kind: AnomalyKind) -> usize {
+        self.anomalies.iter().filter(|anomaly| anomaly.kind == kind).count()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} rows, nominal sample period {} ms", self.rows, self.sample_period_ms)?;
+        if self.is_clean() {
+            return writeln!(f, "no anomalies");
+        }
+        for anomaly in &self.anomalies {
+            let field = if anomaly.field.is_empty() { String::new() } else { format!(" {}", anomaly.field) };
+            writeln!(f, "  rows {}..{} ({} - {} ms): {}{}, {}",
+                     anomaly.start_row, anomaly.end_row, anomaly.start_timestamp, anomaly.end_timestamp,
+                     anomaly.kind.name(), field, anomaly.detail)?;
+        }
+        Ok(())
+    }
+}
+
+// Columns with a physically plausible range. Every float column is listed so NaN and infinity are caught
+// too, the ones without a meaningful limit get an infinite range.
+const RANGES: [(&str, Column, f64, f64); 18] = [
+    ("ax", |rd| rd.ax, f64::NEG_INFINITY, f64::INFINITY),
+    ("ay", |rd| rd.ay, f64::NEG_INFINITY, f64::INFINITY),
+    ("az", |rd| rd.az, f64::NEG_INFINITY, f64::INFINITY),
+    ("gx", |rd| rd.gx, f64::NEG_INFINITY, f64::INFINITY),
+    ("gy", |rd| rd.gy, f64::NEG_INFINITY, f64::INFINITY),
+    ("gz", |rd| rd.gz, f64::NEG_INFINITY, f64::INFINITY),
+    ("mx", |rd| rd.mx, f64::NEG_INFINITY, f64::INFINITY),
+    ("my", |rd| rd.my, f64::NEG_INFINITY, f64::INFINITY),
+    ("mz", |rd| rd.mz, f64::NEG_INFINITY, f64::INFINITY),
+    ("latitude", |rd| rd.latitude, -90.0, 90.0),
+    ("longitude", |rd| rd.longitude, -180.0, 180.0),
+    ("altitude", |rd| rd.altitude, -500.0, 30_000.0),
+    ("satellite_count", |rd| rd.satellite_count as f64, 0.0, 32.0),
+    ("temperature", |rd| rd.temperature, -40.0, 85.0),    // C, rated range of the sensor
+    ("pressure", |rd| rd.pressure, 10.0, 1100.0),          // hPa
+    ("barometer_altitude", |rd| rd.barometer_altitude, -500.0, 30_000.0),
+    ("l1_extension", |rd| rd.l1_extension, f64::NEG_INFINITY, f64::INFINITY),
+    ("l2_extension", |rd| rd.l2_extension, f64::NEG_INFINITY, f64::INFINITY),
+];
+
+const ACCEL_AXES: [(&str, Column); 3] = [("ax", |rd| rd.ax), ("ay", |rd| rd.ay), ("az", |rd| rd.az)];
+const GYRO_AXES: [(&str, Column); 3] = [("gx", |rd| rd.gx), ("gy", |rd| rd.gy), ("gz", |rd| rd.gz)];
+
+pub fn accel_saturated(rd: &RocketData) -> bool {
+    ACCEL_AXES.iter().any(|(_, value)| value(rd).abs() > ACCEL_SATURATION_G)
+}
+
+type SameReadings = fn(&RocketData, &RocketData) -> bool;
+
+// Real sensors always have some noise in the last digit, so a group matching the previous row exactly means
+// the logger wrote a stale sample for it. The logger runs faster than the sensors update, so a group is only
+// frozen once it stays unchanged for longer than its number of nominal sample periods.
+const SENSOR_GROUPS: [(&str, SameReadings, u32); 4] = [
+    ("baro", |a, b| a.pressure == b.pressure && a.temperature == b.temperature, 10),
+    ("imu", |a, b| {
+        a.ax == b.ax && a.ay == b.ay && a.az == b.az
+            && a.gx == b.gx && a.gy == b.gy && a.gz == b.gz
+            && a.mx == b.mx && a.my == b.my && a.mz == b.mz
+    }, 10),
+    // the receiver only updates a few times a second and holds its last position without a fix
+    ("gps", |a, b| {
+        b.position_lock != 0 && a.latitude == b.latitude && a.longitude == b.longitude && a.altitude == b.altitude
+    }, 100),
+    // stowed flaps read exactly zero for as long as they are not commanded out
+    ("airbrake", |a, b| {
+        (b.l1_extension != 0.0 || b.l2_extension != 0.0) && a.l1_extension == b.l1_extension && a.l2_extension == b.l2_extension
+    }, 10),
+];
+
+// Median step between consecutive timestamps, in ms.
+fn nominal_period_ms(rows: &[RocketData]) -> u32 {
+    let mut steps: Vec<u32> = rows.windows(2).map(|w| w[1].timestamp.wrapping_sub(w[0].timestamp)).collect();
+    steps.sort_unstable();
+    steps.get(steps.len() / 2).copied().unwrap_or(10)
+}
+
+// Ranges of consecutive rows for which `flagged` holds, as (start, exclusive end).
+fn runs(rows: &[RocketData], flagged: impl Fn(usize, &RocketData) -> bool) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (i, rd) in rows.iter().enumerate().filter(|(i, rd)| flagged(*i, rd)) {
+        match runs.last_mut() {
+            Some(run) if run.1 == i => run.1 = i + 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+    runs
+}
+
+fn anomaly(rows: &[RocketData], kind: AnomalyKind, field: &'static str, (start, end): (usize, usize), detail: String) -> Anomaly {
+    Anomaly {
+        kind,
+        field,
+        start_row: start,
+        end_row: end,
+        start_timestamp: rows[start].timestamp,
+        end_timestamp: rows[end - 1].timestamp,
+        detail,
+    }
+}
+
+pub fn validate(rows: &[RocketData]) -> ValidationReport {
+    let period = nominal_period_ms(rows);
+    let mut anomalies = Vec::new();
+
+    for (group, same, frozen_periods) in SENSOR_GROUPS.iter() {
+        let min_frozen_ms = frozen_periods * period;
+        // each run starts at the last fresh sample so it covers the whole frozen span
+        for (start, end) in runs(rows, |i, rd| i > 0 && same(&rows[i - 1], rd)) {
+            let frozen_ms = rows[end - 1].timestamp.saturating_sub(rows[start - 1].timestamp);
+            if frozen_ms > min_frozen_ms {
+                let detail = format!("unchanged for {} ms over {} rows, more than {} ms", frozen_ms, end - start + 1, min_frozen_ms);
+                anomalies.push(anomaly(rows, AnomalyKind::RepeatedSample, group, (start - 1, end), detail));
+            }
+        }
+    }
+
+    for (start, end) in runs(rows, |i, rd| i > 0 && rd.timestamp <= rows[i - 1].timestamp) {
+        let detail = format!("{} rows at or before the previous timestamp", end - start);
+        anomalies.push(anomaly(rows, AnomalyKind::TimestampNotIncreasing, "timestamp", (start, end), detail));
+    }
+    for (i, w) in rows.windows(2).enumerate() {
+        let step = w[1].timestamp.saturating_sub(w[0].timestamp);
+        if step > GAP_PERIODS * period {
+            let detail = format!("{} ms step, about {} rows missing", step, step / period.max(1) - 1);
+            anomalies.push(anomaly(rows, AnomalyKind::TimestampGap, "timestamp", (i, i + 2), detail));
+        }
+    }
+
+    for (field, value, min, max) in RANGES.iter() {
+        for run in runs(rows, |_, rd| !(*min..=*max).contains(&value(rd))) {
+            let detail = format!("{} outside {} to {}", value(&rows[run.0]), min, max);
+            anomalies.push(anomaly(rows, AnomalyKind::OutOfRange, field, run, detail));
+        }
+    }
+
+    // losing lock before the first fix is just the receiver starting up
+    let first_lock = rows.iter().position(|rd| rd.position_lock != 0).unwrap_or(rows.len());
+    for run in runs(rows, |i, rd| i > first_lock && rd.position_lock == 0) {
+        let seconds = (rows[run.1 - 1].timestamp.saturating_sub(rows[run.0].timestamp) + period) as f64 / 1000.0;
+        let detail = format!("no fix for {:.2} s, {} satellites", seconds, rows[run.0].satellite_count);
+        anomalies.push(anomaly(rows, AnomalyKind::PositionLockLost, "position_lock", run, detail));
+    }
+
+    let saturation = ACCEL_AXES.iter().map(|(field, value)| (field, value, ACCEL_SATURATION_G, "g"))
+        .chain(GYRO_AXES.iter().map(|(field, value)| (field, value, GYRO_SATURATION_DPS, "deg/s")));
+    for (field, value, limit, unit) in saturation {
+        for run in runs(rows, |_, rd| value(rd).abs() > limit) {
+            let peak = rows[run.0..run.1].iter().map(|rd| value(rd).abs()).fold(0.0, f64::max);
+            anomalies.push(anomaly(rows, AnomalyKind::Saturation, field, run, format!("clipped at {:.2} {}", peak, unit)));
+        }
+    }
+
+    anomalies.sort_by_key(|anomaly| anomaly.start_row);
+    ValidationReport { rows: rows.len(), sample_period_ms: period, anomalies }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csvreader::get_rocket_data;
+
+    // 1 s of clean 100 Hz data where every sensor changes on every row
+    fn clean_log() -> Vec<RocketData> {
+        (0..100).map(|i| {
+            let t = i as f64;
+            RocketData {
+                timestamp: 1000 + 10 * i,
+                ax: 0.01 * t, ay: -1.0 + 0.001 * t, az: 0.02 * t,
+                gx: 0.1 * t, gy: 0.2 * t, gz: 0.3 * t,
+                mx: 0.001 * t, my: 0.002 * t, mz: 0.003 * t,
+                latitude: 41.0 + 1e-6 * t, longitude: -89.0 + 1e-6 * t, altitude: 200.0 + 0.1 * t,
+                satellite_count: 12,
+                position_lock: 1,
+                temperature: 20.0 + 0.01 * t,
+                pressure: 1000.0 - 0.01 * t,
+                barometer_altitude: 110.0 + 0.1 * t,
+                ..RocketData::default()
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn clean_log_has_no_anomalies() {
+        assert!(validate(&clean_log()).is_clean());
+    }
+
+    #[test]
+    fn sample_log_has_no_logging_faults() {
+        let report = validate(&get_rocket_data().unwrap());
+        for kind in [AnomalyKind::RepeatedSample, AnomalyKind::TimestampNotIncreasing, AnomalyKind::TimestampGap, AnomalyKind::OutOfRange] {
+            assert_eq!(report.count(kind), 0, "{}", report);
+        }
+        // what is left really happened in flight, the boost clips ay and the receiver drops its fix a few times
+        assert!(report.anomalies.iter().any(|anomaly| anomaly.kind == AnomalyKind::Saturation && anomaly.field == "ay"));
+        assert_eq!(report.count(AnomalyKind::PositionLockLost), 4);
+    }
+
+    #[test]
+    fn reports_a_dropout_gap() {
+        let mut rows = clean_log();
+        rows.drain(50..80);
+        let report = validate(&rows);
+
+        assert_eq!(report.anomalies.len(), 1, "{}", report);
+        let gap = &report.anomalies[0];
+        assert_eq!((gap.kind, gap.start_row, gap.end_row), (AnomalyKind::TimestampGap, 49, 51));
+        assert_eq!(gap.detail, "310 ms step, about 30 rows missing");
+    }
+
+    #[test]
+    fn reports_a_timestamp_stepping_back() {
+        let mut rows = clean_log();
+        rows[60].timestamp += 10;
+        rows[61].timestamp -= 10;
+        let report = validate(&rows);
+
+        assert_eq!(report.anomalies.len(), 1, "{}", report);
+        let step = &report.anomalies[0];
+        assert_eq!((step.kind, step.start_row, step.end_row), (AnomalyKind::TimestampNotIncreasing, 61, 62));
+    }
+
+    #[test]
+    fn reports_only_the_frozen_sensor_group() {
+        let mut rows = clean_log();
+        for i in 21..51 {
+            rows[i].pressure = rows[20].pressure;
+            rows[i].temperature = rows[20].temperature;
+        }
+        // a few rows of repeats is the logger outrunning the IMU, not a fault
+        for i in 71..74 {
+            rows[i].ax = rows[70].ax; rows[i].ay = rows[70].ay; rows[i].az = rows[70].az;
+            rows[i].gx = rows[70].gx; rows[i].gy = rows[70].gy; rows[i].gz = rows[70].gz;
+            rows[i].mx = rows[70].mx; rows[i].my = rows[70].my; rows[i].mz = rows[70].mz;
+        }
+        let report = validate(&rows);
+
+        assert_eq!(report.anomalies.len(), 1, "{}", report);
+        let frozen = &report.anomalies[0];
+        assert_eq!((frozen.kind, frozen.field, frozen.start_row, frozen.end_row), (AnomalyKind::RepeatedSample, "baro", 20, 51));
+        assert_eq!(frozen.detail, "unchanged for 300 ms over 31 rows, more than 100 ms");
+    }
+}
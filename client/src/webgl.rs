@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+
+use nalgebra_glm as glm;
 use wasm_bindgen::{JsCast, JsValue};
 use web_sys::*;
 
+const STANDARD_ATTRIBUTES: [&str; 3] = ["position", "normal", "uv"];
+
 pub fn get_canvas() -> Option<web_sys::HtmlCanvasElement> {
     let document = web_sys::window().unwrap().document().unwrap();
     let canvas = document.get_element_by_id("canvas").unwrap();
@@ -32,6 +37,80 @@ pub fn make_program(ctx: &WebGl2RenderingContext, vert_code: &str, frag_code: &s
     Ok(program)
 }
 
+// A linked program with the locations of its active uniforms and attributes looked up once after linking.
+// Setters for names the compiler optimised away are no-ops, the same as passing a null location to GL.
+pub struct ShaderProgram {
+    pub program: WebGlProgram,
+    uniforms: HashMap<String, WebGlUniformLocation>,
+    attributes: HashMap<String, u32>,
+}
+
+impl ShaderProgram {
+    pub fn new(ctx: &WebGl2RenderingContext, vert_code: &str, frag_code: &str) -> Result<ShaderProgram, String> {
+        let program = make_program(ctx, vert_code, frag_code)?;
+
+        let mut uniforms = HashMap::new();
+        let uniform_count = ctx.get_program_parameter(&program, WebGl2RenderingContext::ACTIVE_UNIFORMS).as_f64().unwrap_or(0.0) as u32;
+        for i in 0..uniform_count {
+            if let Some(info) = ctx.get_active_uniform(&program, i) {
+                if let Some(location) = ctx.get_uniform_location(&program, &info.name()) {
+                    // arrays are reported as "name[0]", but are set through their plain name
+                    uniforms.insert(info.name().trim_end_matches("[0]").to_string(), location);
+                }
+            }
+        }
+
+        let mut attributes = HashMap::new();
+        let attribute_count = ctx.get_program_parameter(&program, WebGl2RenderingContext::ACTIVE_ATTRIBUTES).as_f64().unwrap_or(0.0) as u32;
+        for i in 0..attribute_count {
+            if let Some(info) = ctx.get_active_attrib(&program, i) {
+                let location = ctx.get_attrib_location(&program, &info.name());
+                if location >= 0 {
+                    attributes.insert(info.name(), location as u32);
+                }
+            }
+        }
+
+        Ok(ShaderProgram {
+            program,
+            uniforms,
+            attributes,
+        })
+    }
+
+    pub fn use_program(&self, ctx: &WebGl2RenderingContext) {
+        ctx.use_program(Some(&self.program));
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&WebGlUniformLocation> {
+        self.uniforms.get(name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<u32> {
+        self.attributes.get(name).copied()
+    }
+
+    pub fn set_f32(&self, ctx: &WebGl2RenderingContext, name: &str, value: f32) {
+        ctx.uniform1f(self.uniform(name), value);
+    }
+
+    pub fn set_vec3(&self, ctx: &WebGl2RenderingContext, name: &str, value: &glm::Vec3) {
+        ctx.uniform3fv_with_f32_array(self.uniform(name), value.data.as_slice());
+    }
+
+    pub fn set_vec4(&self, ctx: &WebGl2RenderingContext, name: &str, value: &glm::Vec4) {
+        ctx.uniform4fv_with_f32_array(self.uniform(name), value.data.as_slice());
+    }
+
+    pub fn set_mat4(&self, ctx: &WebGl2RenderingContext, name: &str, value: &glm::Mat4) {
+        ctx.uniform_matrix4fv_with_f32_array(self.uniform(name), false, value.data.as_slice());
+    }
+
+    pub fn set_texture(&self, ctx: &WebGl2RenderingContext, name: &str, texture: Option<&WebGlTexture>, texture_binding: u32) {
+        bind_shader_texture(ctx, texture, self.uniform(name), texture_binding);
+    }
+}
+
 pub fn make_buffer(ctx: &WebGl2RenderingContext, data: &[f32]) -> WebGlBuffer {
     let buffer = ctx.create_buffer().expect("Failed to create buffer");
     ctx.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
@@ -49,6 +128,21 @@ pub fn make_buffer(ctx: &WebGl2RenderingContext, data: &[f32]) -> WebGlBuffer {
     return buffer;
 }
 
+// Replaces the contents of a buffer that is rewritten every frame.
+pub fn update_buffer(ctx: &WebGl2RenderingContext, buffer: &WebGlBuffer, data: &[f32]) {
+    ctx.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(buffer));
+
+    unsafe {
+        let array_buf_view = js_sys::Float32Array::view(data);
+
+        ctx.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &array_buf_view,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        );
+    }
+}
+
 fn compile_shader(
     context: &WebGl2RenderingContext,
     shader_type: u32,
@@ -84,6 +178,11 @@ fn link_program(
 
     context.attach_shader(&program, vert_shader);
     context.attach_shader(&program, frag_shader);
+    // Vaos are built once, so every program has to agree on where the mesh attributes live, including
+    // programs recompiled after the vaos were made.
+    for (i, name) in STANDARD_ATTRIBUTES.iter().enumerate() {
+        context.bind_attrib_location(&program, i as u32, name);
+    }
     context.link_program(&program);
 
     if context
@@ -104,17 +203,25 @@ pub fn make_vao(ctx: &WebGl2RenderingContext) -> Option<WebGlVertexArrayObject>
 }
 
 pub fn make_texture(ctx: &WebGl2RenderingContext, data: &[u8], width: i32, height: i32) -> Option<WebGlTexture> {
+    make_texture_with_format(ctx, data, width, height, WebGl2RenderingContext::RGB)
+}
+
+pub fn make_texture_rgba(ctx: &WebGl2RenderingContext, data: &[u8], width: i32, height: i32) -> Option<WebGlTexture> {
+    make_texture_with_format(ctx, data, width, height, WebGl2RenderingContext::RGBA)
+}
+
+fn make_texture_with_format(ctx: &WebGl2RenderingContext, data: &[u8], width: i32, height: i32, format: u32) -> Option<WebGlTexture> {
     let texture = ctx.create_texture();
     ctx.bind_texture(WebGl2RenderingContext::TEXTURE_2D, texture.as_ref());
 
     ctx.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
         WebGl2RenderingContext::TEXTURE_2D, //target
         0, //level
-        WebGl2RenderingContext::RGB as i32, //internal format
+        format as i32, //internal format
         width, //width
         height, //height
         0, //border
-        WebGl2RenderingContext::RGB, //format
+        format, //format
         WebGl2RenderingContext::UNSIGNED_BYTE, //type
         Some(data) //data
     ).ok()?;
@@ -144,29 +251,118 @@ pub fn bind_shader_array(ctx: &WebGl2RenderingContext, buffer: Option<&WebGlBuff
     ctx.enable_vertex_attrib_array(location);
 }
 
+// Binds `float_per_instance` floats at `offset` into interleaved records of `stride` floats, advancing once
+// per instance rather than per vertex.
+pub fn bind_instance_array(ctx: &WebGl2RenderingContext, buffer: Option<&WebGlBuffer>, location: u32, float_per_instance: i32, stride: i32, offset: i32) {
+    ctx.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, buffer);
+    ctx.vertex_attrib_pointer_with_i32(location, float_per_instance, WebGl2RenderingContext::FLOAT, false, stride * 4, offset * 4);
+    ctx.enable_vertex_attrib_array(location);
+    ctx.vertex_attrib_divisor(location, 1);
+}
+
 pub fn bind_shader_texture(ctx: &WebGl2RenderingContext, texture: Option<&WebGlTexture>, location: Option<&web_sys::WebGlUniformLocation>, texture_binding: u32){
     ctx.active_texture(WebGl2RenderingContext::TEXTURE0 + texture_binding);
     ctx.bind_texture(WebGl2RenderingContext::TEXTURE_2D, texture);
     ctx.uniform1i(location, texture_binding as i32);
 }
 
-pub fn make_vao_vert_norm_uv(context: &WebGl2RenderingContext, program: &WebGlProgram, vertices: &Vec<f32>, normals: &Vec<f32>, uvs: &Vec<f32>) -> Result<(WebGlVertexArrayObject, i32), JsValue> {
-    let position_attribute_location = context.get_attrib_location(&program, "position");
-    let normal_attribute_location = context.get_attrib_location(&program, "normal");
-    let uv_attribute_location = context.get_attrib_location(&program, "uv");
-
-    let vertex_buffer = make_buffer(&context, vertices.as_slice());
-    let normal_buffer = make_buffer(&context, normals.as_slice());
-    let uv_buffer = make_buffer(&context, uvs.as_slice());
-
+// Attributes the program does not use are left out of the vao, so one mesh layout serves every shader.
+pub fn make_vao_vert_norm_uv(context: &WebGl2RenderingContext, program: &ShaderProgram, vertices: &Vec<f32>, normals: &Vec<f32>, uvs: &Vec<f32>) -> Result<(WebGlVertexArrayObject, i32), JsValue> {
     let vao = make_vao(context).unwrap();
     context.bind_vertex_array(Some(&vao));
 
-    bind_shader_array(context, Some(&vertex_buffer), position_attribute_location as u32, 3);
+    if let Some(location) = program.attribute("position") {
+        bind_shader_array(context, Some(&make_buffer(context, vertices.as_slice())), location, 3);
+    }
 
-    bind_shader_array(context, Some(&normal_buffer), normal_attribute_location as u32, 3);
+    if let Some(location) = program.attribute("normal") {
+        bind_shader_array(context, Some(&make_buffer(context, normals.as_slice())), location, 3);
+    }
 
-    bind_shader_array(context, Some(&uv_buffer), uv_attribute_location as u32, 2);
+    if let Some(location) = program.attribute("uv") {
+        bind_shader_array(context, Some(&make_buffer(context, uvs.as_slice())), location, 2);
+    }
 
     return Ok((vao, (vertices.len()/3) as i32));
 }
+
+pub fn make_vao_vert(context: &WebGl2RenderingContext, program: &ShaderProgram, vertices: &[f32]) -> Result<(WebGlVertexArrayObject, i32), JsValue> {
+    let vao = make_vao(context).ok_or_else(|| JsValue::from_str("Unable to create vertex array"))?;
+    context.bind_vertex_array(Some(&vao));
+
+    if let Some(location) = program.attribute("position") {
+        bind_shader_array(context, Some(&make_buffer(context, vertices)), location, 3);
+    }
+
+    Ok((vao, (vertices.len()/3) as i32))
+}
+
+pub fn make_depth_framebuffer(ctx: &WebGl2RenderingContext, width: i32, height: i32) -> Option<(WebGlFramebuffer, Option<WebGlTexture>)> {
+    let texture = ctx.create_texture();
+    ctx.bind_texture(WebGl2RenderingContext::TEXTURE_2D, texture.as_ref());
+    ctx.tex_storage_2d(WebGl2RenderingContext::TEXTURE_2D, 1, WebGl2RenderingContext::DEPTH_COMPONENT24, width, height);
+    // compare mode lets the shader sample with sampler2DShadow and get hardware filtered lookups
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_COMPARE_MODE, WebGl2RenderingContext::COMPARE_REF_TO_TEXTURE as i32);
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_COMPARE_FUNC, WebGl2RenderingContext::LEQUAL as i32);
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR as i32);
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR as i32);
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+
+    let framebuffer = ctx.create_framebuffer()?;
+    ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&framebuffer));
+    ctx.framebuffer_texture_2d(
+        WebGl2RenderingContext::FRAMEBUFFER,
+        WebGl2RenderingContext::DEPTH_ATTACHMENT,
+        WebGl2RenderingContext::TEXTURE_2D,
+        texture.as_ref(),
+        0
+    );
+    let status = ctx.check_framebuffer_status(WebGl2RenderingContext::FRAMEBUFFER);
+    ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+    if status != WebGl2RenderingContext::FRAMEBUFFER_COMPLETE {
+        return None;
+    }
+
+    return Some((framebuffer, texture));
+}
+
+pub fn make_renderbuffer(ctx: &WebGl2RenderingContext, internal_format: u32, width: i32, height: i32) -> Option<WebGlRenderbuffer> {
+    let renderbuffer = ctx.create_renderbuffer()?;
+    ctx.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, Some(&renderbuffer));
+    ctx.renderbuffer_storage(WebGl2RenderingContext::RENDERBUFFER, internal_format, width, height);
+    ctx.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, None);
+    Some(renderbuffer)
+}
+
+// Framebuffer drawing into a colour and a depth renderbuffer, for rendering off the screen.
+pub fn make_framebuffer(ctx: &WebGl2RenderingContext, color: &WebGlRenderbuffer, depth: &WebGlRenderbuffer) -> Option<WebGlFramebuffer> {
+    let framebuffer = ctx.create_framebuffer()?;
+    ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&framebuffer));
+    ctx.framebuffer_renderbuffer(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::COLOR_ATTACHMENT0, WebGl2RenderingContext::RENDERBUFFER, Some(color));
+    ctx.framebuffer_renderbuffer(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::DEPTH_ATTACHMENT, WebGl2RenderingContext::RENDERBUFFER, Some(depth));
+    let status = ctx.check_framebuffer_status(WebGl2RenderingContext::FRAMEBUFFER);
+    ctx.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+    if status != WebGl2RenderingContext::FRAMEBUFFER_COMPLETE {
+        ctx.delete_framebuffer(Some(&framebuffer));
+        return None;
+    }
+    Some(framebuffer)
+}
+
+// RGBA bytes of the bound framebuffer with the top row first, as image formats store them.
+pub fn read_rgba_pixels(ctx: &WebGl2RenderingContext, width: i32, height: i32) -> Result<Vec<u8>, JsValue> {
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    ctx.pixel_storei(WebGl2RenderingContext::PACK_ALIGNMENT, 1);
+    ctx.read_pixels_with_opt_u8_array(0, 0, width, height, WebGl2RenderingContext::RGBA, WebGl2RenderingContext::UNSIGNED_BYTE, Some(&mut pixels))?;
+
+    // gl rows start at the bottom
+    let row = (width * 4) as usize;
+    let mut flipped = Vec::with_capacity(pixels.len());
+    for y in (0..height as usize).rev() {
+        flipped.extend_from_slice(&pixels[y * row..(y + 1) * row]);
+    }
+    Ok(flipped)
+}
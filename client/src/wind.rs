@@ -0,0 +1,93 @@
+use crate::kalman::AltitudeEstimate;
+use crate::recovery::{ground_track, TrackPoint, DRIFT_WINDOW_SECONDS, MIN_DESCENT_RATE};
+use crate::rocket_data::RocketData;
+
+pub const WIND_BAND_METERS: f64 = 200.0;
+
+// Mean wind over one altitude band, heights above the pad.
+#[derive(Clone, Copy, Debug)]
+pub struct WindBand {
+    pub bottom: f64,      // m
+    pub top: f64,         // m
+    pub east: f64,        // m/s, towards
+    pub north: f64,       // m/s, towards
+    pub samples: usize,
+}
+
+impl WindBand {
+    pub fn speed(&self) -> f64 {
+        self.east.hypot(self.north)
+    }
+
+    // Meteorological direction, degrees clockwise from north that the wind blows from.
+    pub fn direction(&self) -> f64 {
+        (-self.east).atan2(-self.north).to_degrees().rem_euclid(360.0)
+    }
+}
+
+// Under a parachute the rocket drifts with the air, so the horizontal GPS velocity after apogee is the
+// wind. Each fix is paired with the first one DRIFT_WINDOW_SECONDS later while descending, and the
+// velocity between them is filed under the band of their mean height above the pad. The windows overlap,
+// so neighbouring samples are not independent.
+pub fn estimate_wind(rows: &[RocketData], altitude: &[AltitudeEstimate], band_meters: f64) -> Vec<WindBand> {
+    let (pad, ground) = match (rows.iter().find(|rd| rd.position_lock != 0), altitude.first()) {
+        (Some(pad), Some(ground)) if band_meters > 0.0 => (pad, ground.altitude),
+        _ => return Vec::new(),
+    };
+    let apogee_row = altitude.iter().enumerate()
+        .max_by(|(_, a), (_, b)| a.altitude.total_cmp(&b.altitude))
+        .map(|(row, _)| row)
+        .unwrap_or(0);
+
+    let descending = |point: &TrackPoint| point.row > apogee_row && altitude.get(point.row).is_some_and(|e| e.velocity < -MIN_DESCENT_RATE);
+    let track: Vec<TrackPoint> = ground_track(rows, pad.latitude, pad.longitude).into_iter().filter(descending).collect();
+
+    let window_ms = (DRIFT_WINDOW_SECONDS * 1000.0) as u32;
+    let mut sums: Vec<(f64, f64, usize)> = Vec::new();
+    for (i, start) in track.iter().enumerate() {
+        // timestamps that step back can leave a later start with an end, so keep looking past a miss
+        let end = match track[i..].iter().find(|point| point.timestamp >= start.timestamp.saturating_add(window_ms)) {
+            Some(end) => end,
+            None => continue,
+        };
+        // a long gap means the fixes straddle a dropout or the end of the descent, and a pair with no time
+        // between them has no velocity
+        let seconds = end.timestamp.saturating_sub(start.timestamp) as f64 / 1000.0;
+        if seconds <= 0.0 || seconds > 2.0 * DRIFT_WINDOW_SECONDS {
+            continue;
+        }
+        let height = (altitude[start.row].altitude + altitude[end.row].altitude) / 2.0 - ground;
+        if height < 0.0 {
+            continue;
+        }
+        let band = (height / band_meters) as usize;
+        if sums.len() <= band {
+            sums.resize(band + 1, (0.0, 0.0, 0));
+        }
+        sums[band].0 += (end.east - start.east) / seconds;
+        sums[band].1 += (end.north - start.north) / seconds;
+        sums[band].2 += 1;
+    }
+
+    sums.iter().enumerate()
+        .filter(|(_, (_, _, samples))| *samples > 0)
+        .map(|(band, (east, north, samples))| WindBand {
+            bottom: band as f64 * band_meters,
+            top: (band + 1) as f64 * band_meters,
+            east: east / *samples as f64,
+            north: north / *samples as f64,
+            samples: *samples,
+        })
+        .collect()
+}
+
+// Wind table for simulators, one band per line in SI units with the direction the wind comes from.
+pub fn wind_profile_csv(bands: &[WindBand]) -> String {
+    let mut text = "altitude_agl_m,band_bottom_m,band_top_m,speed_m_s,direction_from_deg,east_m_s,north_m_s,samples\n".to_string();
+    for band in bands {
+        text += &format!("{:.0},{:.0},{:.0},{:.2},{:.0},{:.2},{:.2},{}\n",
+                         (band.bottom + band.top) / 2.0, band.bottom, band.top, band.speed(), band.direction(), band.east, band.north, band.samples);
+    }
+    text
+}
+
@@ -0,0 +1,100 @@
+use nalgebra_glm as glm;
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlTexture, WebGlVertexArrayObject};
+
+use crate::load_model::{make_box, Mesh};
+use crate::webgl::{make_texture, make_vao_vert_norm_uv, ShaderProgram};
+use crate::wind::WindBand;
+
+// Arrows stand this far west of the trajectory, one per band at its middle height, pointing downwind.
+const ARROW_OFFSET_METERS: f32 = 4.0;
+const ARROW_METERS_PER_MPS: f32 = 0.5;
+const SHAFT_WIDTH: f32 = 0.1;
+const HEAD_WIDTH: f32 = 0.3;
+const HEAD_LENGTH: f32 = 0.25; // fraction of the arrow
+
+// Wind profile from the descent drawn as a column of arrows beside the rocket's path.
+pub struct WindArrows {
+    vao: WebGlVertexArrayObject,
+    vertex_count: i32,
+    texture: Option<WebGlTexture>,
+    // model matrix and heading of each arrow
+    arrows: Vec<(glm::Mat4, glm::Mat4)>,
+}
+
+impl WindArrows {
+    // `ground_altitude` is the pad altitude in the scene, which the band heights are measured from.
+    pub fn new(context: &WebGl2RenderingContext, program: &ShaderProgram, bands: &[WindBand], ground_altitude: f32) -> Result<WindArrows, JsValue> {
+        let (verts, norms, uvs) = make_arrow();
+        let (vao, vertex_count) = make_vao_vert_norm_uv(context, program, &verts, &norms, &uvs)?;
+        // BGR, like the bmp textures
+        let texture = make_texture(context, &[230, 200, 60], 1, 1);
+
+        let arrows = bands.iter().map(|band| {
+            let height = ground_altitude + ((band.bottom + band.top) / 2.0) as f32;
+            let heading = glm::rotate(&glm::identity(), band.north.atan2(band.east) as f32, &glm::vec3(0.0, 0.0, 1.0));
+            let length = band.speed() as f32 * ARROW_METERS_PER_MPS;
+            let model = glm::translate(&glm::identity(), &glm::vec3(-ARROW_OFFSET_METERS, 0.0, height))
+                * heading
+                * glm::scale(&glm::identity(), &glm::vec3(length, 1.0, 1.0));
+            (model, heading)
+        }).collect();
+
+        Ok(WindArrows { vao, vertex_count, texture, arrows })
+    }
+
+    // Draws with the rocket program already in use.
+    pub fn render(&self, ctx: &WebGl2RenderingContext, program: &ShaderProgram, view_projection: &glm::Mat4) {
+        if self.arrows.is_empty() {
+            return;
+        }
+        program.set_texture(ctx, "tex", self.texture.as_ref(), 0);
+        program.set_mat4(ctx, "light_mvp", &glm::identity());
+        program.set_f32(ctx, "receive_shadow", 0.0);
+        program.set_f32(ctx, "opacity", 1.0);
+        ctx.bind_vertex_array(Some(&self.vao));
+        for (model, heading) in &self.arrows {
+            program.set_mat4(ctx, "mvp", &(view_projection * model));
+            program.set_mat4(ctx, "rotate", heading);
+            ctx.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, self.vertex_count);
+        }
+    }
+}
+
+// Unit length arrow along +x centred on the origin: a square shaft and a pyramid head.
+fn make_arrow() -> Mesh {
+    let uv = [0.5, 0.5];
+    let shaft_end = 0.5 - HEAD_LENGTH;
+    let (shaft_half, head_half) = (SHAFT_WIDTH / 2.0, HEAD_WIDTH / 2.0);
+    let mut mesh = make_box(&glm::vec3(-0.5, -shaft_half, -shaft_half), &glm::vec3(shaft_end, shaft_half, shaft_half), uv);
+
+    let apex = glm::vec3(0.5, 0.0, 0.0);
+    let base = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+        .iter()
+        .map(|(y, z)| glm::vec3(shaft_end, y * head_half, z * head_half))
+        .collect::<Vec<_>>();
+    let centre = glm::vec3(shaft_end + HEAD_LENGTH / 4.0, 0.0, 0.0);
+    let mut triangles = Vec::new();
+    for i in 0..4 {
+        triangles.push([base[i], base[(i + 1) % 4], apex]);
+    }
+    triangles.push([base[0], base[1], base[2]]);
+    triangles.push([base[0], base[2], base[3]]);
+
+    for [a, b, c] in triangles {
+        // wind each face counter clockwise seen from outside the head
+        let mut normal = glm::normalize(&glm::cross(&(b - a), &(c - a)));
+        let (b, c) = if glm::dot(&normal, &(a - centre)) < 0.0 {
+            normal = -normal;
+            (c, b)
+        } else {
+            (b, c)
+        };
+        for p in [a, b, c].iter() {
+            mesh.0.extend_from_slice(&[p.x, p.y, p.z]);
+            mesh.1.extend_from_slice(&[normal.x, normal.y, normal.z]);
+            mesh.2.extend_from_slice(&uv);
+        }
+    }
+    mesh
+}